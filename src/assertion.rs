@@ -0,0 +1,228 @@
+use crate::parser::transaction::{Amount, Posting, Transaction};
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, HashMap};
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+#[error("Balance assertion failed on {account}: expected {expected}, but got {actual}")]
+pub struct AssertionError<'a> {
+    pub account: &'a str,
+    pub expected: Amount<'a>,
+    pub actual: Amount<'a>,
+}
+
+/// Running balance of every account, per commodity.
+///
+/// Transactions must be fed in the order they appear in the journal.
+/// Processing a transaction takes three steps:
+///
+/// 1. `resolve_assignments` computes the amounts of postings which only
+///    have a balance assignment like `Assets:Cash  = 3000 JPY`,
+/// 2. `balancer::balance_transaction` infers the elided amount,
+/// 3. `apply` adds the postings to the balances and checks the assertions.
+///
+/// An assignment or an assertion without unit whose value is zero, like
+/// `= 0`, refers to every commodity held in the account.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RunningBalance<'a> {
+    accounts: HashMap<&'a str, BTreeMap<&'a str, Decimal>>,
+}
+
+impl<'a> RunningBalance<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the balance of `account` in `unit`.
+    pub fn balance(&self, account: &str, unit: &str) -> Decimal {
+        self.accounts.get(account)
+            .and_then(|b| b.get(unit))
+            .copied()
+            .unwrap_or(Decimal::ZERO)
+    }
+
+    /// Returns the non-zero balances of `account` sorted by unit.
+    pub fn balances(&self, account: &str) -> Vec<Amount<'a>> {
+        self.accounts.get(account)
+            .map(|b| {
+                b.iter()
+                    .filter(|(_, price)| !price.is_zero())
+                    .map(|(unit, price)| Amount::new(*price, unit))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn add(&mut self, account: &'a str, amount: &Amount<'a>) {
+        *self.accounts.entry(account)
+            .or_default()
+            .entry(amount.unit)
+            .or_insert(Decimal::ZERO) += amount.price;
+    }
+
+    /// Fills in the amounts of postings which have a balance assignment but
+    /// no amount.
+    ///
+    /// Postings earlier in the same transaction are taken into account.
+    pub fn resolve_assignments(&self, tx: &mut Transaction<'a>) {
+        let mut scratch = self.clone();
+        let mut i = 0;
+
+        while i < tx.posting.len() {
+            let posting = &tx.posting[i];
+            let resolved = match (&posting.amount, &posting.assign) {
+                (None, Some(assign)) => {
+                    // Only the last posting asserts the assigned balance
+                    let amounts = scratch.amounts_to_reach(posting.account, assign);
+                    let last = amounts.len() - 1;
+                    amounts.into_iter()
+                        .enumerate()
+                        .map(|(j, a)| Posting {
+                            amount: Some(a),
+                            assign: if j == last { posting.assign.clone() } else { None },
+                            ..posting.clone()
+                        })
+                        .collect()
+                },
+                _ => vec![posting.clone()],
+            };
+
+            let n = resolved.len();
+            for p in resolved.iter() {
+                if let Some(amount) = &p.amount {
+                    scratch.add(p.account, amount);
+                }
+            }
+            tx.posting.splice(i..=i, resolved);
+            i += n;
+        }
+    }
+
+    // Computes the amounts which bring `account` to the balance `assign`.
+    fn amounts_to_reach(&self, account: &'a str, assign: &Amount<'a>) -> Vec<Amount<'a>> {
+        if assign.unit.is_empty() && assign.price.is_zero() {
+            let amounts: Vec<_> = self.balances(account)
+                .into_iter()
+                .map(|a| Amount::new(-a.price, a.unit))
+                .collect();
+            if amounts.is_empty() {
+                vec![assign.clone()]
+            } else {
+                amounts
+            }
+        } else {
+            let current = self.balance(account, assign.unit);
+            vec![Amount::new(assign.price - current, assign.unit)]
+        }
+    }
+
+    /// Adds the postings of a balanced transaction and checks the balance
+    /// assertions on them.
+    ///
+    /// The balances are updated up to the posting whose assertion fails.
+    pub fn apply(&mut self, tx: &Transaction<'a>) -> Result<(), AssertionError<'a>> {
+        for posting in tx.posting.iter() {
+            if let Some(amount) = &posting.amount {
+                self.add(posting.account, amount);
+            }
+
+            if let Some(assign) = &posting.assign {
+                self.check(posting.account, assign)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check(&self, account: &'a str, expected: &Amount<'a>) -> Result<(), AssertionError<'a>> {
+        let actual = if expected.unit.is_empty() && expected.price.is_zero() {
+            self.balances(account)
+                .into_iter()
+                .next()
+                .unwrap_or_else(|| expected.clone())
+        } else {
+            Amount::new(self.balance(account, expected.unit), expected.unit)
+        };
+
+        if actual.price == expected.price {
+            Ok(())
+        } else {
+            Err(AssertionError {
+                account,
+                expected: expected.clone(),
+                actual,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::balancer::balance_transaction;
+    use crate::parser::transaction::transaction;
+
+    fn process<'a>(rb: &mut RunningBalance<'a>, s: &'a str) -> Result<Transaction<'a>, AssertionError<'a>> {
+        let (_, mut tx) = transaction(s).unwrap();
+        rb.resolve_assignments(&mut tx);
+        balance_transaction(&mut tx).unwrap();
+        rb.apply(&tx).map(|_| tx)
+    }
+
+    #[test]
+    fn check_balance_assertion() {
+        let mut rb = RunningBalance::new();
+        process(&mut rb, r#"2021-09-16 * 引き出し
+    資産:現金           1000 JPY = 1000 JPY
+    資産:普通預金:JP
+"#).unwrap();
+        let err = process(&mut rb, r#"2021-09-17 * 引き出し
+    資産:現金           1000 JPY = 3000 JPY
+    資産:普通預金:JP
+"#).unwrap_err();
+        assert_eq!(
+            err,
+            AssertionError {
+                account: "資産:現金",
+                expected: Amount::from_str("3000", "JPY").unwrap(),
+                actual: Amount::from_str("2000", "JPY").unwrap(),
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Balance assertion failed on 資産:現金: expected 3000 JPY, but got 2000 JPY"
+        );
+    }
+
+    #[test]
+    fn resolve_balance_assignment() {
+        let mut rb = RunningBalance::new();
+        process(&mut rb, r#"2021-09-16 * 引き出し
+    資産:現金           1000 JPY
+    資産:普通預金:JP
+"#).unwrap();
+        let tx = process(&mut rb, r#"2021-09-30 * 棚卸し
+    資産:現金           = 700 JPY
+    費用:雑費
+"#).unwrap();
+        assert_eq!(tx.posting[0].amount, Amount::from_str("-300", "JPY").ok());
+        assert_eq!(tx.posting[1].amount, Amount::from_str("300", "JPY").ok());
+        assert_eq!(rb.balance("資産:現金", "JPY"), Decimal::from(700));
+    }
+
+    #[test]
+    fn assign_zero_to_every_commodity() {
+        let mut rb = RunningBalance::new();
+        process(&mut rb, r#"2021-09-16 * 両替
+    資産:現金           1000 JPY
+    資産:現金           10 USD
+    資産:普通預金:JP
+"#).unwrap();
+        let tx = process(&mut rb, r#"2021-09-30 * 預け入れ
+    資産:現金           =0
+    資産:普通預金:JP
+"#).unwrap();
+        assert_eq!(tx.posting.len(), 4);
+        assert!(rb.balances("資産:現金").is_empty());
+    }
+}
@@ -0,0 +1,132 @@
+use crate::balancer::Precisions;
+use crate::parser::automated::AutomatedTransaction;
+use crate::parser::transaction::{Amount, Posting, Transaction};
+use crate::query::{Query, QueryError};
+use rust_decimal::prelude::RoundingStrategy;
+use rust_decimal::Decimal;
+
+/// Automated transaction whose query is ready to match postings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutomatedRule<'a> {
+    pub query: Query,
+    /// Postings added for each matched posting
+    pub posting: Vec<Posting<'a>>,
+}
+
+impl<'a> AutomatedRule<'a> {
+    pub fn new(auto: AutomatedTransaction<'a>) -> Result<Self, QueryError> {
+        Ok(Self {
+            query: Query::parse(&auto.query)?,
+            posting: auto.posting,
+        })
+    }
+
+    /// Returns the postings generated for a posting of `tx`, which are none
+    /// if it does not match the query.
+    ///
+    /// A multiplier is applied to the amount of the matched posting and the
+    /// product is rounded at the precision of its commodity if declared. The
+    /// generated postings take the dates of the matched posting.
+    pub fn generate(&self, tx: &Transaction<'a>, posting: &Posting<'a>, precisions: &Precisions) -> Vec<Posting<'a>> {
+        if !self.query.matches(tx, posting) {
+            return Vec::new();
+        }
+
+        self.posting.iter()
+            .map(|template| {
+                let amount = match (&template.amount, &posting.amount) {
+                    (Some(factor), Some(matched)) if factor.unit.is_empty() => {
+                        Some(multiply(matched, factor.price, precisions))
+                    },
+                    (amount, _) => amount.clone(),
+                };
+                let mut generated = Posting {
+                    amount,
+                    date: template.date.or(posting.date),
+                    edate: template.edate.or(posting.edate),
+                    ..template.clone()
+                };
+                generated.tags.inherit(&tx.header.tags);
+                generated
+            })
+            .collect()
+    }
+}
+
+/// Adds the postings generated by `rules` for the postings of a balanced
+/// transaction.
+///
+/// The postings added by a rule are not matched by the rules again.
+pub fn expand<'a>(rules: &[AutomatedRule<'a>], tx: &mut Transaction<'a>, precisions: &Precisions) {
+    let mut generated = Vec::new();
+
+    for rule in rules.iter() {
+        for posting in tx.posting.iter() {
+            generated.extend(rule.generate(tx, posting, precisions));
+        }
+    }
+
+    tx.posting.extend(generated);
+}
+
+// Multiplies an amount, keeping at least its decimal places unless the
+// commodity has a fixed precision.
+fn multiply<'a>(amount: &Amount<'a>, factor: Decimal, precisions: &Precisions) -> Amount<'a> {
+    let mut price = amount.price * factor;
+
+    match precisions.get(amount.unit) {
+        Some(&dp) => price = price.round_dp_with_strategy(dp, RoundingStrategy::MidpointAwayFromZero),
+        None => {
+            price = price.normalize();
+            if price.scale() < amount.price.scale() {
+                price.rescale(amount.price.scale());
+            }
+        },
+    }
+
+    Amount::new(price, amount.unit)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::automated::automated_transaction;
+    use crate::parser::transaction::transaction;
+
+    fn expanded(rule: &str, tx: &str, precisions: &Precisions) -> Vec<String> {
+        let rules = vec![AutomatedRule::new(automated_transaction(rule).unwrap().1).unwrap()];
+        let mut tx = transaction(tx).unwrap().1;
+        expand(&rules, &mut tx, precisions);
+
+        tx.posting.iter()
+            .map(|p| format!("{} {}", p.account, p.amount.as_ref().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn add_postings_for_matched_postings() {
+        let rule = "= /^費用:/\n    (予算:食費)  -1\n    (予算:残高)  100 JPY\n";
+        let tx = "2021-09-20 * Tomod's\n    費用:食費  500 JPY\n    資産:現金  -500 JPY\n";
+
+        assert_eq!(
+            expanded(rule, tx, &Precisions::new()),
+            vec!["費用:食費 500 JPY", "資産:現金 -500 JPY", "予算:食費 -500 JPY", "予算:残高 100 JPY"]
+        );
+    }
+
+    #[test]
+    fn round_multiplied_amounts() {
+        let rule = "= 費用 desc:ドラッグストア\n    費用:消費税  0.0909\n    費用:日用品  -0.0909\n";
+        let tx = "2021-09-25 * ドラッグストア\n    費用:日用品  1100 JPY\n    資産:現金  -1100 JPY\n";
+        let precisions = vec![("JPY", 0)].into_iter().collect();
+
+        assert_eq!(
+            expanded(rule, tx, &precisions),
+            vec!["費用:日用品 1100 JPY", "資産:現金 -1100 JPY", "費用:消費税 100 JPY", "費用:日用品 -100 JPY"]
+        );
+        assert_eq!(
+            expanded(rule, tx, &Precisions::new())[2..],
+            ["費用:消費税 99.99 JPY", "費用:日用品 -99.99 JPY"]
+        );
+    }
+}
@@ -0,0 +1,310 @@
+use crate::parser::transaction::{Amount, Posting, PostingKind, Transaction};
+use rust_decimal::{Decimal, RoundingStrategy};
+use std::collections::{BTreeMap, HashMap};
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum BalanceError<'a> {
+    #[error("Only one posting with null amount allowed per transaction")]
+    MultipleElided,
+    #[error("Transaction does not balance: residual {}", format_residual(.0))]
+    Unbalanced(Vec<Amount<'a>>),
+}
+
+fn format_residual(residual: &[Amount]) -> String {
+    residual.iter()
+        .map(|a| a.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Returns the amount which a posting contributes to its transaction.
+///
+/// A posting with a cost like `1 VTI @ 12300 JPY` is weighted in the
+/// commodity of the cost. The cost of a lot annotation like `{12300 JPY}`
+/// takes precedence, so that selling a lot at a price other than its cost
+/// leaves the capital gain to be balanced by another posting. `None` is
+/// returned if the amount is elided.
+pub fn posting_weight<'a>(posting: &Posting<'a>) -> Option<Amount<'a>> {
+    let amount = posting.amount.as_ref()?;
+    let lot_cost = posting.lot.as_ref().and_then(|lot| lot.cost.as_ref());
+
+    Some(match (lot_cost, &posting.cost) {
+        (Some(cost), _) => Amount::new(amount.price * cost.price, cost.unit),
+        (None, Some(cost)) => cost.total(amount.price),
+        (None, None) => amount.clone(),
+    })
+}
+
+/// Number of decimal places of commodities with a fixed precision, like 0
+/// for JPY.
+pub type Precisions<'a> = HashMap<&'a str, u32>;
+
+/// Computes the per-commodity sum of the posting weights of a transaction.
+///
+/// Commodities whose sum is zero are omitted and the rest are sorted by unit.
+/// Postings with an elided amount and virtual postings are ignored.
+pub fn residual<'a>(tx: &Transaction<'a>) -> Vec<Amount<'a>> {
+    residual_with(tx, &Precisions::new())
+}
+
+/// Computes the residual of a transaction like `residual`, rounding the sum
+/// in each commodity with a fixed precision.
+///
+/// Weights converted by costs may have more decimal places than their
+/// commodity, like `0.5 USD @ 111.5 JPY`. Halves are rounded away from zero.
+pub fn residual_with<'a>(tx: &Transaction<'a>, precisions: &Precisions) -> Vec<Amount<'a>> {
+    residual_of(tx, PostingKind::Real, precisions)
+}
+
+// Computes the residual of the postings of `kind` in a transaction.
+fn residual_of<'a>(tx: &Transaction<'a>, kind: PostingKind, precisions: &Precisions) -> Vec<Amount<'a>> {
+    let mut sum = BTreeMap::new();
+    let postings = tx.posting.iter().filter(|p| p.kind == kind);
+
+    for weight in postings.filter_map(posting_weight) {
+        *sum.entry(weight.unit).or_insert(Decimal::ZERO) += weight.price;
+    }
+
+    sum.into_iter()
+        .map(|(unit, price)| match precisions.get(unit) {
+            Some(&dp) => (unit, price.round_dp_with_strategy(dp, RoundingStrategy::MidpointAwayFromZero)),
+            None => (unit, price),
+        })
+        .filter(|(_, price)| !price.is_zero())
+        .map(|(unit, price)| Amount::new(price, unit))
+        .collect()
+}
+
+/// Balances a transaction in place.
+///
+/// If a posting has an elided amount, it is replaced with one posting per
+/// commodity which brings the transaction to zero. An elided posting gets
+/// a zero amount when the other postings already balance. Balance
+/// assignments must be resolved before calling this function, otherwise
+/// the postings carrying them are treated as elided.
+///
+/// Balanced virtual postings like `[Budget:Food]` are balanced among
+/// themselves in the same way, apart from the real postings. Virtual
+/// postings like `(Budget:Food)` do not have to balance, but an elided
+/// one among them is inferred from the other virtual postings likewise.
+pub fn balance_transaction<'a>(tx: &mut Transaction<'a>) -> Result<(), BalanceError<'a>> {
+    balance_transaction_with(tx, &Precisions::new())
+}
+
+/// Balances a transaction in place like `balance_transaction`, rounding the
+/// residual in commodities with a fixed precision.
+pub fn balance_transaction_with<'a>(tx: &mut Transaction<'a>, precisions: &Precisions) -> Result<(), BalanceError<'a>> {
+    balance_postings(tx, PostingKind::Real, precisions)?;
+    balance_postings(tx, PostingKind::BalancedVirtual, precisions)?;
+    balance_postings(tx, PostingKind::Virtual, precisions)
+}
+
+// Balances the postings of `kind` in a transaction.
+fn balance_postings<'a>(tx: &mut Transaction<'a>, kind: PostingKind, precisions: &Precisions) -> Result<(), BalanceError<'a>> {
+    let mut elided = tx.posting.iter()
+        .enumerate()
+        .filter(|(_, p)| p.kind == kind && p.amount.is_none())
+        .map(|(i, _)| i);
+    let index = elided.next();
+
+    if elided.next().is_some() {
+        return Err(BalanceError::MultipleElided);
+    }
+
+    let residual = residual_of(tx, kind, precisions);
+
+    match index {
+        Some(i) if residual.is_empty() => {
+            tx.posting[i].amount = Some(Amount::new(Decimal::ZERO, ""));
+            Ok(())
+        },
+        Some(i) => {
+            let template = tx.posting[i].clone();
+            let inferred = residual.into_iter()
+                .map(|a| Posting {
+                    amount: Some(Amount::new(-a.price, a.unit)),
+                    ..template.clone()
+                })
+                .collect::<Vec<_>>();
+            tx.posting.splice(i..=i, inferred);
+            Ok(())
+        },
+        None if residual.is_empty() || kind == PostingKind::Virtual => Ok(()),
+        None => Err(BalanceError::Unbalanced(residual)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::transaction::transaction;
+
+    fn balanced(s: &str) -> Result<Transaction<'_>, BalanceError<'_>> {
+        let (_, mut tx) = transaction(s).unwrap();
+        balance_transaction(&mut tx).map(|_| tx)
+    }
+
+    fn amounts<'a>(tx: &Transaction<'a>) -> Vec<Option<Amount<'a>>> {
+        tx.posting.iter().map(|p| p.amount.clone()).collect()
+    }
+
+    #[test]
+    fn infer_elided_amount() {
+        let tx = balanced(r#"2021-09-20 * Tomod's
+    費用:食費           500 JPY
+    費用:消耗品費       1000 JPY
+    資産:現金
+"#).unwrap();
+        assert_eq!(
+            amounts(&tx),
+            vec![
+                Amount::from_str("500", "JPY").ok(),
+                Amount::from_str("1000", "JPY").ok(),
+                Amount::from_str("-1500", "JPY").ok(),
+            ]
+        );
+    }
+
+    #[test]
+    fn split_elided_amount_per_commodity() {
+        let tx = balanced(r#"2021-09-20 * Exchange
+    資産:現金           500 JPY
+    資産:現金           10 USD
+    資産:普通預金
+"#).unwrap();
+        assert_eq!(tx.posting.len(), 4);
+        assert_eq!(tx.posting[2].account, "資産:普通預金");
+        assert_eq!(tx.posting[3].account, "資産:普通預金");
+        assert_eq!(
+            amounts(&tx)[2..],
+            [
+                Amount::from_str("-500", "JPY").ok(),
+                Amount::from_str("-10", "USD").ok(),
+            ]
+        );
+    }
+
+    #[test]
+    fn weight_posting_by_cost() {
+        let tx = balanced(r#"2021-03-01 * Buy
+    資産:ETF            2 VTI @ 12300 JPY
+    資産:証券口座
+"#).unwrap();
+        assert_eq!(tx.posting[1].amount, Amount::from_str("-24600", "JPY").ok());
+    }
+
+    #[test]
+    fn weight_posting_by_lot_cost() {
+        let tx = balanced(r#"2021-09-16 * Sell
+    資産:ETF            -2 VTI {12300 JPY} @ 23000 JPY
+    資産:証券口座       46000 JPY
+    収益:譲渡益
+"#).unwrap();
+        assert_eq!(tx.posting[2].amount, Amount::from_str("-21400", "JPY").ok());
+    }
+
+    #[test]
+    fn weight_posting_by_total_cost() {
+        let tx = balanced(r#"2021-03-01 * Sell
+    資産:ETF            -3 VTI @@ 100000 JPY
+    資産:証券口座
+"#).unwrap();
+        assert_eq!(tx.posting[1].amount, Amount::from_str("100000", "JPY").ok());
+    }
+
+    #[test]
+    fn round_residual_in_fixed_precision() {
+        let s = r#"2021-09-16 * 両替
+    資産:外貨           0.5 USD @ 111.5 JPY
+    資産:現金           -56 JPY
+"#;
+        let precisions: Precisions = vec![("JPY", 0)].into_iter().collect();
+        let (_, mut tx) = transaction(s).unwrap();
+
+        assert!(balance_transaction(&mut tx.clone()).is_err());
+        assert_eq!(balance_transaction_with(&mut tx, &precisions), Ok(()));
+
+        let (_, mut tx) = transaction("2021-09-16 * 両替\n    資産:外貨  3 USD @ 33.333 JPY\n    資産:現金\n").unwrap();
+        balance_transaction_with(&mut tx, &precisions).unwrap();
+        assert_eq!(tx.posting[1].amount, Amount::from_str("-100", "JPY").ok());
+    }
+
+    #[test]
+    fn balance_virtual_postings_separately() {
+        let tx = balanced(r#"2021-09-20 * Tomod's
+    費用:食費           500 JPY
+    資産:現金
+    (予算:食費)         -500 JPY
+    [予算:日用品]       -300 JPY
+    [予算:未割当]
+"#).unwrap();
+        assert_eq!(
+            amounts(&tx),
+            [
+                Amount::from_str("500", "JPY").ok(),
+                Amount::from_str("-500", "JPY").ok(),
+                Amount::from_str("-500", "JPY").ok(),
+                Amount::from_str("-300", "JPY").ok(),
+                Amount::from_str("300", "JPY").ok(),
+            ]
+        );
+
+        let err = balanced("2021-09-20 * Tomod's\n    費用:食費  500 JPY\n    資産:現金  -500 JPY\n    [予算:食費]  -500 JPY\n").unwrap_err();
+        assert_eq!(err, BalanceError::Unbalanced(vec![Amount::from_str("-500", "JPY").unwrap()]));
+    }
+
+    #[test]
+    fn infer_elided_virtual_amount() {
+        let tx = balanced(r#"2021-09-20 * Tomod's
+    費用:食費           500 JPY
+    資産:現金
+    (予算:食費)         -500 JPY
+    (予算:未割当)
+"#).unwrap();
+        assert_eq!(tx.posting[3].amount, Amount::from_str("500", "JPY").ok());
+
+        let tx = balanced("2021-09-20 * Tomod's\n    費用:食費  500 JPY\n    資産:現金\n    (予算:食費)\n").unwrap();
+        assert_eq!(tx.posting[2].amount, Amount::from_str("0", "").ok());
+    }
+
+    #[test]
+    fn set_zero_to_elided_amount_of_balanced_transaction() {
+        let tx = balanced(r#"2021-09-16 * 引き出し
+    資産:現金           1000 JPY
+    資産:普通預金:JP    -1000 JPY
+    資産:外貨
+"#).unwrap();
+        assert_eq!(tx.posting[2].amount, Amount::from_str("0", "").ok());
+    }
+
+    #[test]
+    fn reject_unbalanced_transaction() {
+        let err = balanced(r#"2021-09-16 * 引き出し
+    資産:現金           1000 JPY
+    資産:普通預金:JP    -900 JPY
+    資産:外貨           5 USD
+"#).unwrap_err();
+        assert_eq!(
+            err,
+            BalanceError::Unbalanced(vec![
+                Amount::from_str("100", "JPY").unwrap(),
+                Amount::from_str("5", "USD").unwrap(),
+            ])
+        );
+        assert_eq!(
+            err.to_string(),
+            "Transaction does not balance: residual 100 JPY, 5 USD"
+        );
+    }
+
+    #[test]
+    fn reject_multiple_elided_postings() {
+        let err = balanced(r#"2021-09-16 * 引き出し
+    資産:現金           1000 JPY
+    資産:普通預金:JP
+    資産:外貨
+"#).unwrap_err();
+        assert_eq!(err, BalanceError::MultipleElided);
+    }
+}
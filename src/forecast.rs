@@ -0,0 +1,108 @@
+use crate::parser::periodic::PeriodicTransaction;
+use crate::parser::transaction::{Status, Transaction, TransactionHeader};
+use crate::period::Period;
+use chrono::NaiveDate;
+
+/// Tag given to the transactions generated by `forecast`.
+pub const FORECAST_TAG: &str = "forecast";
+
+/// Returns the dates from `begin` up to but not including `end` on which a
+/// transaction recurring in `period` occurs.
+///
+/// Occurrences are counted from the beginning of the period, or from the
+/// start of the interval containing `begin` if the period is open. A period
+/// without an interval occurs once on its beginning.
+pub fn occurrences(period: &Period, begin: NaiveDate, end: NaiveDate) -> Vec<NaiveDate> {
+    let end = period.span.end.map_or(end, |e| e.min(end));
+
+    match period.interval {
+        Some(interval) => {
+            let anchor = period.span.begin.unwrap_or_else(|| interval.start(begin));
+            (0..)
+                .map_while(|n| interval.add(anchor, n))
+                .take_while(|date| *date < end)
+                .filter(|date| *date >= begin)
+                .collect()
+        },
+        None => period.span.begin
+            .filter(|date| begin <= *date && *date < end)
+            .into_iter()
+            .collect(),
+    }
+}
+
+/// Materializes a periodic transaction into the transactions occurring in
+/// `period` from `begin` up to but not including `end`.
+///
+/// The transactions and their postings are tagged with `FORECAST_TAG`.
+pub fn forecast<'a>(periodic: &PeriodicTransaction<'a>, period: &Period, begin: NaiveDate, end: NaiveDate) -> Vec<Transaction<'a>> {
+    occurrences(period, begin, end).into_iter()
+        .map(|date| {
+            let mut tags = periodic.tags.clone();
+            tags.insert(FORECAST_TAG, None);
+            let posting = periodic.posting.iter()
+                .map(|p| {
+                    let mut p = p.clone();
+                    p.tags.insert(FORECAST_TAG, None);
+                    p
+                })
+                .collect();
+
+            Transaction {
+                header: TransactionHeader {
+                    date,
+                    edate: None,
+                    status: Status::Uncleared,
+                    code: None,
+                    description: periodic.description.clone(),
+                    comment: periodic.comment.clone(),
+                    notes: periodic.notes.clone(),
+                    tags,
+                },
+                posting,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::periodic::periodic_transaction;
+
+    fn ymd(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn dates(period: &str, begin: NaiveDate, end: NaiveDate) -> Vec<String> {
+        let period = Period::parse(period, ymd(2021, 9, 16)).unwrap();
+        occurrences(&period, begin, end).iter().map(|d| d.to_string()).collect()
+    }
+
+    #[test]
+    fn count_occurrences() {
+        assert_eq!(
+            dates("monthly from 2021-04-25", ymd(2021, 9, 17), ymd(2022, 1, 1)),
+            vec!["2021-09-25", "2021-10-25", "2021-11-25", "2021-12-25"]
+        );
+        assert_eq!(
+            dates("monthly from 2021-01-31 to 2021-06", ymd(2021, 1, 1), ymd(2022, 1, 1)),
+            vec!["2021-01-31", "2021-02-28", "2021-03-31", "2021-04-30", "2021-05-31"]
+        );
+        assert_eq!(dates("quarterly", ymd(2021, 9, 17), ymd(2022, 1, 1)), vec!["2021-10-01"]);
+        assert_eq!(dates("2021-12-10", ymd(2021, 9, 17), ymd(2022, 1, 1)), vec!["2021-12-10"]);
+    }
+
+    #[test]
+    fn generate_transactions() {
+        let src = "~ monthly from 2021-04  家賃\n    費用:家賃  80000 JPY\n    資産:普通預金\n";
+        let periodic = periodic_transaction(src).unwrap().1;
+        let period = Period::parse(&periodic.period, ymd(2021, 9, 16)).unwrap();
+        let transactions = forecast(&periodic, &period, ymd(2021, 9, 17), ymd(2021, 12, 1));
+
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(transactions[0].header.date, ymd(2021, 10, 1));
+        assert_eq!(transactions[0].header.description, "家賃");
+        assert!(transactions[1].posting[0].tags.contains(FORECAST_TAG));
+    }
+}
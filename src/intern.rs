@@ -0,0 +1,251 @@
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::sync::{Mutex, OnceLock};
+
+static STRINGS: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+
+/// Returns a string equal to `s` which lives until the program exits.
+///
+/// Equal strings share a single allocation, so names repeated throughout a
+/// journal like accounts and commodities are stored once. Interned strings
+/// are never freed, so only names should be interned, not free text like
+/// descriptions or notes.
+pub fn intern(s: &str) -> &'static str {
+    let mut strings = STRINGS.get_or_init(Default::default).lock().unwrap();
+
+    if let Some(interned) = strings.get(s) {
+        return interned;
+    }
+    let interned: &'static str = Box::leak(s.to_owned().into_boxed_str());
+    strings.insert(interned);
+    interned
+}
+
+/// Handle of an account in an `AccountTable`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct AccountId(u32);
+
+/// Handle of a commodity in a `CommodityTable`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CommodityId(u32);
+
+impl AccountId {
+    /// Returns the position of the account in its table, which is less than
+    /// the length of the table.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl CommodityId {
+    /// Returns the position of the commodity in its table, which is less
+    /// than the length of the table.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Ids of the names in a posting.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PostingIds {
+    pub account: AccountId,
+    /// Commodity of the amount, or `None` if the posting has no amount
+    pub commodity: Option<CommodityId>,
+    /// Commodity of the lot cost or the price, in which the book value of
+    /// the amount is
+    pub cost: Option<CommodityId>,
+}
+
+fn next_id(len: usize) -> u32 {
+    u32::try_from(len).expect("too many names to intern")
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct AccountEntry<'a> {
+    name: &'a str,
+    parent: Option<AccountId>,
+    children: Vec<AccountId>,
+}
+
+/// Accounts numbered by `AccountId` and arranged in a tree.
+///
+/// An account like `資産:普通預金:JP` is the child of `資産:普通預金`, which
+/// is added to the table together with its own parents.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AccountTable<'a> {
+    entries: Vec<AccountEntry<'a>>,
+    index: HashMap<&'a str, AccountId>,
+}
+
+impl<'a> AccountTable<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the id of an account, adding it and its parents if absent.
+    pub fn intern(&mut self, name: &'a str) -> AccountId {
+        if let Some(&id) = self.index.get(name) {
+            return id;
+        }
+
+        let parent = name.rfind(':').map(|i| self.intern(&name[..i]));
+        let id = AccountId(next_id(self.entries.len()));
+        self.entries.push(AccountEntry {
+            name,
+            parent,
+            children: Vec::new(),
+        });
+        self.index.insert(name, id);
+        if let Some(parent) = parent {
+            self.entries[parent.index()].children.push(id);
+        }
+
+        id
+    }
+
+    pub fn get(&self, name: &str) -> Option<AccountId> {
+        self.index.get(name).copied()
+    }
+
+    /// Returns the full name of an account like `資産:普通預金:JP`.
+    pub fn name(&self, id: AccountId) -> &'a str {
+        self.entries[id.index()].name
+    }
+
+    /// Returns the last segment of the name of an account like `JP`.
+    pub fn leaf(&self, id: AccountId) -> &'a str {
+        let name = self.name(id);
+        name.rsplit(':').next().unwrap_or(name)
+    }
+
+    pub fn parent(&self, id: AccountId) -> Option<AccountId> {
+        self.entries[id.index()].parent
+    }
+
+    /// Returns the children of an account in the order they were added.
+    pub fn children(&self, id: AccountId) -> &[AccountId] {
+        &self.entries[id.index()].children
+    }
+
+    /// Iterates over the accounts without a parent in the order they were
+    /// added.
+    pub fn roots(&self) -> impl Iterator<Item = AccountId> + '_ {
+        self.iter().map(|(id, _)| id).filter(move |&id| self.parent(id).is_none())
+    }
+
+    /// Iterates over an account and its parents up to the root.
+    pub fn ancestors(&self, id: AccountId) -> impl Iterator<Item = AccountId> + '_ {
+        std::iter::successors(Some(id), move |&id| self.parent(id))
+    }
+
+    /// Iterates over the accounts with their names in the order they were
+    /// added.
+    pub fn iter(&self) -> impl Iterator<Item = (AccountId, &'a str)> + '_ {
+        self.entries.iter()
+            .enumerate()
+            .map(|(i, entry)| (AccountId(i as u32), entry.name))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Commodities numbered by `CommodityId`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CommodityTable<'a> {
+    units: Vec<&'a str>,
+    index: HashMap<&'a str, CommodityId>,
+}
+
+impl<'a> CommodityTable<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the id of a commodity, adding it if absent.
+    pub fn intern(&mut self, unit: &'a str) -> CommodityId {
+        if let Some(&id) = self.index.get(unit) {
+            return id;
+        }
+
+        let id = CommodityId(next_id(self.units.len()));
+        self.units.push(unit);
+        self.index.insert(unit, id);
+        id
+    }
+
+    pub fn get(&self, unit: &str) -> Option<CommodityId> {
+        self.index.get(unit).copied()
+    }
+
+    pub fn unit(&self, id: CommodityId) -> &'a str {
+        self.units[id.index()]
+    }
+
+    /// Iterates over the commodities with their units in the order they
+    /// were added.
+    pub fn iter(&self) -> impl Iterator<Item = (CommodityId, &'a str)> + '_ {
+        self.units.iter()
+            .enumerate()
+            .map(|(i, unit)| (CommodityId(i as u32), *unit))
+    }
+
+    pub fn len(&self) -> usize {
+        self.units.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.units.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn share_equal_strings() {
+        let a = intern(&String::from("資産:現金"));
+        let b = intern(&String::from("資産:現金"));
+
+        assert_eq!(a, "資産:現金");
+        assert!(std::ptr::eq(a, b));
+        assert!(!std::ptr::eq(a, intern("資産:普通預金")));
+    }
+
+    #[test]
+    fn build_account_tree() {
+        let mut table = AccountTable::new();
+        let jp = table.intern("資産:普通預金:JP");
+        let cash = table.intern("資産:現金");
+        let food = table.intern("費用:食費");
+
+        assert_eq!(table.len(), 6);
+        assert_eq!(table.intern("資産:現金"), cash);
+        let assets = table.get("資産").unwrap();
+        let names = |ids: Vec<AccountId>| ids.into_iter().map(|id| table.name(id)).collect::<Vec<_>>();
+        assert_eq!(names(table.roots().collect()), vec!["資産", "費用"]);
+        assert_eq!(names(table.children(assets).to_vec()), vec!["資産:普通預金", "資産:現金"]);
+        assert_eq!(names(table.ancestors(jp).collect()), vec!["資産:普通預金:JP", "資産:普通預金", "資産"]);
+        assert_eq!(table.leaf(jp), "JP");
+        assert_eq!(table.parent(food), table.get("費用"));
+        assert_eq!(table.get("資産:普通"), None);
+    }
+
+    #[test]
+    fn number_commodities() {
+        let mut table = CommodityTable::new();
+        let jpy = table.intern("JPY");
+        let usd = table.intern("USD");
+
+        assert_eq!(table.intern("JPY"), jpy);
+        assert_eq!((jpy.index(), usd.index()), (0, 1));
+        assert_eq!(table.unit(usd), "USD");
+        assert_eq!(table.get("EUR"), None);
+    }
+}
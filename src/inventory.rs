@@ -0,0 +1,380 @@
+use crate::parser::transaction::{Amount, Lot, Posting, Transaction};
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum InventoryError<'a> {
+    #[error("No lot of {unit} in {account} matches {lot}")]
+    NoMatchingLot {
+        account: &'a str,
+        unit: &'a str,
+        lot: Lot<'a>,
+    },
+    #[error("Cannot reduce {amount} from {account}: only {held} {} held in lots", .amount.unit)]
+    InsufficientLots {
+        account: &'a str,
+        amount: Amount<'a>,
+        held: Decimal,
+    },
+}
+
+/// Method to choose the lots reduced by a sale without a lot annotation.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Booking {
+    /// The oldest lots first
+    #[default]
+    Fifo,
+    /// The newest lots first
+    Lifo,
+    /// The oldest lots first, at the average cost of the lots held, which
+    /// becomes the cost of the lots left
+    Average,
+}
+
+/// Quantity of a commodity held in an account at a cost.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeldLot<'a> {
+    pub quantity: Decimal,
+    /// Per-unit cost
+    pub cost: Amount<'a>,
+    /// Date on which the lot was acquired
+    pub date: NaiveDate,
+    pub note: Option<Cow<'a, str>>,
+}
+
+impl<'a> HeldLot<'a> {
+    // Returns true if every part given in `lot` is the same as this lot.
+    fn matches(&self, lot: &Lot<'a>) -> bool {
+        lot.cost.as_ref().is_none_or(|c| *c == self.cost) &&
+            lot.date.is_none_or(|d| d == self.date) &&
+            lot.note.as_ref().is_none_or(|n| Some(n) == self.note.as_ref())
+    }
+}
+
+/// Capital gain realized by selling (a part of) a lot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Realized<'a> {
+    /// Date of the sale
+    pub date: NaiveDate,
+    pub account: &'a str,
+    /// Quantity sold, which is always positive
+    pub amount: Amount<'a>,
+    /// Date on which the lot was acquired
+    pub acquired: NaiveDate,
+    /// Total cost of the quantity sold
+    pub cost: Amount<'a>,
+    /// Total price at which the quantity was sold
+    pub proceeds: Amount<'a>,
+}
+
+impl<'a> Realized<'a> {
+    /// Returns the gain, or `None` if the cost and the proceeds are in
+    /// different commodities.
+    pub fn gain(&self) -> Option<Amount<'a>> {
+        if self.cost.unit == self.proceeds.unit {
+            Some(Amount::new(self.proceeds.price - self.cost.price, self.cost.unit))
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a> fmt::Display for Realized<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} (acquired {}) cost {} proceeds {}",
+            self.date.format("%Y-%m-%d"),
+            self.account,
+            self.amount,
+            self.acquired.format("%Y-%m-%d"),
+            self.cost,
+            self.proceeds
+        )?;
+        if let Some(gain) = self.gain() {
+            write!(f, " gain {}", gain)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Lots held in each account and the gains realized by selling them.
+///
+/// A lot is acquired by a posting with a positive amount and a cost, given
+/// either by a lot annotation like `10 VTI {12300 JPY}` or by `@ 12300 JPY`.
+/// A posting with a negative amount reduces the lots of its account which
+/// match its lot annotation, or the lots chosen by the booking method if it
+/// has none. The gain is realized if the sale has a price like
+/// `@ 23000 JPY`; otherwise the lots are just moved out of the account.
+#[derive(Debug, Default, Clone)]
+pub struct Inventory<'a> {
+    booking: Booking,
+    lots: BTreeMap<(&'a str, &'a str), Vec<HeldLot<'a>>>,
+    pub realized: Vec<Realized<'a>>,
+}
+
+impl<'a> Inventory<'a> {
+    pub fn new(booking: Booking) -> Self {
+        Self {
+            booking,
+            ..Self::default()
+        }
+    }
+
+    /// Returns the lots of `unit` held in `account` in the order acquired.
+    pub fn lots(&self, account: &str, unit: &str) -> &[HeldLot<'a>] {
+        self.lots.iter()
+            .find(|((a, u), _)| *a == account && *u == unit)
+            .map_or(&[], |(_, lots)| lots.as_slice())
+    }
+
+    /// Acquires and reduces the lots by the postings of a balanced
+    /// transaction.
+    ///
+    /// On error, the postings before the failing one have been applied.
+    pub fn add_transaction(&mut self, tx: &Transaction<'a>) -> Result<(), InventoryError<'a>> {
+        for posting in tx.posting.iter() {
+            let amount = match &posting.amount {
+                Some(amount) => amount,
+                None => continue,
+            };
+            let key = (posting.account, amount.unit);
+
+            if amount.price.is_zero() {
+                continue;
+            } else if amount.price.is_sign_positive() {
+                let lot_cost = posting.lot.as_ref().and_then(|lot| lot.cost.clone());
+                if let Some(cost) = lot_cost.or_else(|| posting.cost.as_ref().map(|c| c.per_unit(amount.price))) {
+                    let lot = posting.lot.as_ref();
+                    let lots = self.lots.entry(key).or_default();
+                    lots.push(HeldLot {
+                        quantity: amount.price,
+                        cost,
+                        date: lot.and_then(|l| l.date).unwrap_or(tx.header.date),
+                        note: lot.and_then(|l| l.note.clone()),
+                    });
+                    lots.sort_by_key(|l| l.date);
+                }
+            } else if posting.lot.is_some() || self.lots.contains_key(&key) {
+                self.reduce(tx.header.date, posting, amount)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn reduce(&mut self, date: NaiveDate, posting: &Posting<'a>, amount: &Amount<'a>) -> Result<(), InventoryError<'a>> {
+        let lots = self.lots.entry((posting.account, amount.unit)).or_default();
+
+        let mut order: Vec<usize> = match &posting.lot {
+            Some(lot) => {
+                let order: Vec<_> = (0..lots.len()).filter(|&i| lots[i].matches(lot)).collect();
+                if order.is_empty() {
+                    return Err(InventoryError::NoMatchingLot {
+                        account: posting.account,
+                        unit: amount.unit,
+                        lot: lot.clone(),
+                    });
+                }
+                order
+            },
+            None => (0..lots.len()).collect(),
+        };
+        let averages = match (&posting.lot, self.booking) {
+            (None, Booking::Average) => Some(average_costs(lots)),
+            _ => None,
+        };
+        if self.booking == Booking::Lifo {
+            order.reverse();
+        }
+
+        let held: Decimal = order.iter().map(|&i| lots[i].quantity).sum();
+        if held < -amount.price {
+            return Err(InventoryError::InsufficientLots {
+                account: posting.account,
+                amount: amount.clone(),
+                held,
+            });
+        }
+
+        let price = posting.cost.as_ref().map(|c| c.per_unit(amount.price));
+        let mut realized: Vec<Realized<'a>> = Vec::new();
+        let mut remaining = -amount.price;
+        for i in order {
+            if remaining.is_zero() {
+                break;
+            }
+            let lot = &mut lots[i];
+            let quantity = remaining.min(lot.quantity);
+            lot.quantity -= quantity;
+            remaining -= quantity;
+
+            if let Some(price) = &price {
+                let cost = averages.as_ref().map_or(lot.cost.price, |a| a[lot.cost.unit]);
+                let sold = Realized {
+                    date,
+                    account: posting.account,
+                    amount: Amount::new(quantity, amount.unit),
+                    acquired: lot.date,
+                    cost: Amount::new(quantity * cost, lot.cost.unit),
+                    proceeds: Amount::new(quantity * price.price, price.unit),
+                };
+                // A sale at the average cost is a single gain per commodity
+                match realized.iter_mut().find(|r| averages.is_some() && r.cost.unit == sold.cost.unit) {
+                    Some(r) => {
+                        r.amount.price += sold.amount.price;
+                        r.cost.price += sold.cost.price;
+                        r.proceeds.price += sold.proceeds.price;
+                    },
+                    None => realized.push(sold),
+                }
+            }
+        }
+        lots.retain(|lot| !lot.quantity.is_zero());
+        // The lots left by a sale at the average cost are held at that cost
+        if let Some(averages) = &averages {
+            for lot in lots.iter_mut() {
+                lot.cost.price = averages[lot.cost.unit];
+            }
+        }
+        self.realized.extend(realized);
+
+        Ok(())
+    }
+}
+
+// Returns the average per-unit cost of the lots in each cost commodity.
+fn average_costs<'a>(lots: &[HeldLot<'a>]) -> HashMap<&'a str, Decimal> {
+    let mut totals: HashMap<&'a str, (Decimal, Decimal)> = HashMap::new();
+
+    for lot in lots.iter() {
+        let (quantity, cost) = totals.entry(lot.cost.unit).or_default();
+        *quantity += lot.quantity;
+        *cost += lot.quantity * lot.cost.price;
+    }
+
+    totals.into_iter()
+        .map(|(unit, (quantity, cost))| (unit, cost / quantity))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::journal::Journal;
+
+    const LEDGER: &str = r#"2021-03-01 * 買付
+    資産:ETF            10 VTI {12000 JPY}
+    資産:証券口座
+
+2021-06-01 * 買付
+    資産:ETF            10 VTI @ 14000 JPY
+    資産:証券口座
+
+2021-09-16 * 売却
+    資産:ETF            -15 VTI @ 23000 JPY
+    資産:証券口座
+"#;
+
+    fn gains(booking: Booking, src: &str) -> Result<Vec<String>, InventoryError<'_>> {
+        let journal = Journal::parse(src, "a.ledger").unwrap();
+        let mut inventory = Inventory::new(booking);
+        for entry in journal.entries.iter() {
+            inventory.add_transaction(&entry.transaction)?;
+        }
+
+        Ok(inventory.realized.iter()
+            .map(|r| format!("{} {}", r.amount, r.gain().unwrap()))
+            .collect())
+    }
+
+    #[test]
+    fn match_lots_by_booking_method() {
+        assert_eq!(
+            gains(Booking::Fifo, LEDGER).unwrap(),
+            vec!["10 VTI 110000 JPY", "5 VTI 45000 JPY"]
+        );
+        assert_eq!(
+            gains(Booking::Lifo, LEDGER).unwrap(),
+            vec!["10 VTI 90000 JPY", "5 VTI 55000 JPY"]
+        );
+        assert_eq!(gains(Booking::Average, LEDGER).unwrap(), vec!["15 VTI 150000 JPY"]);
+    }
+
+    #[test]
+    fn keep_lots_after_sale_at_average_cost() {
+        let src = format!("{}\n2021-09-20 * 売却\n    資産:ETF  -5 VTI {{13000 JPY}} @ 20000 JPY\n    資産:証券口座\n", LEDGER);
+
+        assert_eq!(
+            gains(Booking::Average, &src).unwrap(),
+            vec!["15 VTI 150000 JPY", "5 VTI 35000 JPY"]
+        );
+    }
+
+    #[test]
+    fn sell_again_at_average_cost() {
+        let src = format!(r#"{}
+2021-10-01 * 買付
+    資産:ETF            5 VTI @ 16000 JPY
+    資産:証券口座
+
+2021-11-01 * 売却
+    資産:ETF            -10 VTI @ 20000 JPY
+    資産:証券口座
+"#, LEDGER);
+
+        assert_eq!(
+            gains(Booking::Average, &src).unwrap(),
+            vec!["15 VTI 150000 JPY", "10 VTI 55000 JPY"]
+        );
+    }
+
+    #[test]
+    fn skip_zero_amounts() {
+        let src = "2021-03-01 * 買付\n    資産:ETF  0 VTI @ 12000 JPY\n    資産:証券口座\n";
+        let journal = Journal::parse(src, "a.ledger").unwrap();
+        let mut inventory = Inventory::new(Booking::Fifo);
+        inventory.add_transaction(&journal.entries[0].transaction).unwrap();
+
+        assert_eq!(inventory.lots("資産:ETF", "VTI"), &[]);
+    }
+
+    #[test]
+    fn match_explicit_lot() {
+        let src = format!(r#"{}
+2021-09-20 * 売却
+    資産:ETF            -5 VTI {{14000 JPY}} @ 20000 JPY
+    資産:証券口座       100000 JPY
+    収益:譲渡益
+"#, LEDGER);
+        let journal = Journal::parse(&src, "a.ledger").unwrap();
+        let mut inventory = Inventory::new(Booking::Fifo);
+        for entry in journal.entries.iter() {
+            inventory.add_transaction(&entry.transaction).unwrap();
+        }
+
+        assert_eq!(inventory.realized.len(), 3);
+        assert_eq!(inventory.realized[2].gain(), Amount::from_str("30000", "JPY").ok());
+        assert_eq!(
+            inventory.realized[2].to_string(),
+            "2021-09-20 資産:ETF 5 VTI (acquired 2021-06-01) cost 70000 JPY proceeds 100000 JPY gain 30000 JPY"
+        );
+        assert_eq!(inventory.lots("資産:ETF", "VTI"), &[]);
+    }
+
+    #[test]
+    fn reject_unknown_lot() {
+        let src = format!("{}\n2021-09-20 * 売却\n    資産:ETF  -5 VTI {{13000 JPY}}\n    資産:証券口座\n", LEDGER);
+        let e = gains(Booking::Fifo, &src).unwrap_err();
+        assert_eq!(e.to_string(), "No lot of VTI in 資産:ETF matches {13000 JPY}");
+
+        let src = LEDGER.replace("-15 VTI", "-25 VTI");
+        let e = gains(Booking::Fifo, &src).unwrap_err();
+        assert_eq!(e.to_string(), "Cannot reduce -25 VTI from 資産:ETF: only 20 VTI held in lots");
+    }
+}
@@ -0,0 +1,664 @@
+use crate::assertion::{AssertionError, RunningBalance};
+use crate::automation::{expand, AutomatedRule};
+use crate::balancer::{balance_transaction_with, BalanceError, Precisions};
+use crate::forecast::forecast;
+use crate::intern::{AccountId, AccountTable, CommodityId, CommodityTable, PostingIds};
+use crate::parser::directive::{AccountDeclaration, CommodityDeclaration};
+use crate::parser::periodic::PeriodicTransaction;
+use crate::parser::transaction::{Amount, Posting, Transaction};
+use crate::parser::{LedgerError, LedgerItem, LedgerParser};
+use crate::period::Period;
+use crate::price::PriceDb;
+use crate::query::QueryError;
+use chrono::NaiveDate;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use thiserror::Error;
+
+/// Position of an item in a journal file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub filename: String,
+    /// Line number at which the item begins
+    pub line: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.filename, self.line)
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum JournalError<'a> {
+    #[error("{0}")]
+    Parse(LedgerError),
+    #[error("{location}: {error}")]
+    Balance {
+        location: Location,
+        error: BalanceError<'a>,
+    },
+    #[error("{location}: {error}")]
+    Assertion {
+        location: Location,
+        error: AssertionError<'a>,
+    },
+    #[error("{location}: Undeclared account {account}")]
+    UndeclaredAccount {
+        location: Location,
+        account: &'a str,
+    },
+    #[error("{location}: Undeclared commodity {unit}")]
+    UndeclaredCommodity {
+        location: Location,
+        unit: &'a str,
+    },
+    #[error("{location}: {error}")]
+    Query {
+        location: Location,
+        error: QueryError,
+    },
+    #[error("{location}: Invalid period {period}")]
+    Period {
+        location: Location,
+        period: Cow<'a, str>,
+    },
+    #[error("{location}: Unknown account or commodity {name}")]
+    UnknownName {
+        location: Location,
+        name: &'a str,
+    },
+    #[error("{location}: Cannot include {path}, which is not loaded")]
+    UnresolvedInclude {
+        location: Location,
+        path: Cow<'a, str>,
+    },
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct JournalOptions {
+    /// Reject postings to accounts or in commodities which are not declared
+    /// by the `account` or `commodity` directives beforehand.
+    pub strict: bool,
+}
+
+/// Balanced transaction with its location and the ids of its postings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry<'a> {
+    pub transaction: Transaction<'a>,
+    pub location: Location,
+    /// Ids of each posting of `transaction`
+    pub posting_ids: Vec<PostingIds>,
+}
+
+/// Balanced transactions and declarations read from journals.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Journal<'a> {
+    /// Transactions in the order added
+    pub entries: Vec<Entry<'a>>,
+    pub accounts: Vec<AccountDeclaration<'a>>,
+    pub commodities: Vec<CommodityDeclaration<'a>>,
+    /// Prices from `P` directives and the costs of postings
+    pub prices: PriceDb<'a>,
+    /// Periodic transactions, which are added by `add_forecast`
+    pub periodic: Vec<(PeriodicTransaction<'a>, Location)>,
+    /// Accounts which are declared or posted to, and their parents
+    pub account_table: AccountTable<'a>,
+    /// Commodities which are declared or used in amounts and prices
+    pub commodity_table: CommodityTable<'a>,
+    options: JournalOptions,
+    running: RunningBalance<'a>,
+    aliases: HashMap<&'a str, &'a str>,
+    declared_accounts: HashSet<AccountId>,
+    declared_commodities: HashSet<CommodityId>,
+    precisions: Precisions<'a>,
+    rules: Vec<AutomatedRule<'a>>,
+}
+
+impl<'a> Journal<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_options(options: JournalOptions) -> Self {
+        Self {
+            options,
+            ..Self::default()
+        }
+    }
+
+    /// Parses a journal and balances its transactions.
+    ///
+    /// Broken items are skipped and every problem found is returned.
+    pub fn parse(src: &'a str, filename: &str) -> Result<Self, Vec<JournalError<'a>>> {
+        let mut journal = Self::new();
+        let errors = journal.read(src, filename);
+
+        if errors.is_empty() {
+            Ok(journal)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Reads the items of a journal text into this journal.
+    ///
+    /// Broken items are skipped and the problems found are returned.
+    /// `include` directives cannot be resolved here; use `loader::Loader` to
+    /// read journals split into several files.
+    pub fn read(&mut self, src: &'a str, filename: &str) -> Vec<JournalError<'a>> {
+        self.read_with(src, filename, |item| item)
+    }
+
+    // Reads the items of a journal text converted by `convert`.
+    fn read_with<'s, F>(&mut self, src: &'s str, filename: &str, convert: F) -> Vec<JournalError<'a>>
+    where
+        F: Fn(LedgerItem<'s>) -> LedgerItem<'a>,
+    {
+        let mut errors = Vec::new();
+        let mut parser = LedgerParser::with_filename(src, filename).recovering();
+
+        loop {
+            let location = Location {
+                filename: filename.to_owned(),
+                line: parser.line(),
+            };
+
+            match parser.next() {
+                None => break,
+                Some(Ok(item)) => {
+                    if let Err(e) = self.add_item(convert(item), location) {
+                        errors.push(e);
+                    }
+                },
+                Some(Err(e)) => errors.push(JournalError::Parse(e)),
+            }
+        }
+
+        errors
+    }
+
+    /// Adds a parsed item located at `location`.
+    pub fn add_item(&mut self, item: LedgerItem<'a>, location: Location) -> Result<(), JournalError<'a>> {
+        match item {
+            LedgerItem::Transaction(tx) => self.add_transaction(tx, location),
+            LedgerItem::Account(decl) => {
+                self.declared_accounts.insert(self.account_table.intern(decl.name));
+                for alias in decl.aliases.iter() {
+                    self.aliases.insert(alias, decl.name);
+                }
+                self.accounts.push(decl);
+                Ok(())
+            },
+            LedgerItem::Commodity(decl) => {
+                self.declared_commodities.insert(self.commodity_table.intern(decl.unit));
+                if let Some(precision) = decl.precision() {
+                    self.precisions.insert(decl.unit, precision);
+                }
+                self.commodities.push(decl);
+                Ok(())
+            },
+            LedgerItem::Price(p) => {
+                self.commodity_table.intern(p.unit);
+                self.commodity_table.intern(p.price.unit);
+                self.prices.add(p.date, p.unit, &p.price);
+                Ok(())
+            },
+            LedgerItem::Automated(auto) => {
+                let rule = AutomatedRule::new(auto).map_err(|error| JournalError::Query { location, error })?;
+                self.rules.push(rule);
+                Ok(())
+            },
+            LedgerItem::Periodic(periodic) => {
+                // The period is resolved when forecasting
+                if !Period::is_valid(&periodic.period) {
+                    return Err(JournalError::Period { location, period: periodic.period });
+                }
+                for posting in periodic.posting.iter() {
+                    self.intern_posting(posting);
+                }
+                self.periodic.push((periodic, location));
+                Ok(())
+            },
+            LedgerItem::Include(path) => Err(JournalError::UnresolvedInclude { location, path }),
+            LedgerItem::Blank => Ok(()),
+        }
+    }
+
+    /// Resolves aliases, balances a transaction and checks its assertions.
+    ///
+    /// Amounts in commodities declared with a `format` are balanced at the
+    /// precision of the format. The postings of the automated transactions
+    /// read so far are added to the balanced transaction, and must balance
+    /// by themselves. A transaction which does not balance is not added.
+    pub fn add_transaction(&mut self, mut tx: Transaction<'a>, location: Location) -> Result<(), JournalError<'a>> {
+        for posting in tx.posting.iter_mut() {
+            if let Some(name) = self.aliases.get(posting.account) {
+                posting.account = name;
+            }
+        }
+
+        self.running.resolve_assignments(&mut tx);
+        if let Err(error) = balance_transaction_with(&mut tx, &self.precisions) {
+            return Err(JournalError::Balance { location, error });
+        }
+        if !self.rules.is_empty() {
+            expand(&self.rules, &mut tx, &self.precisions);
+            if let Err(error) = balance_transaction_with(&mut tx, &self.precisions) {
+                return Err(JournalError::Balance { location, error });
+            }
+        }
+
+        if self.options.strict {
+            self.check_declared(&tx, &location)?;
+        }
+        let posting_ids = tx.posting.iter().map(|p| self.intern_posting(p)).collect();
+
+        for posting in tx.posting.iter() {
+            if let Some(amount) = &posting.amount {
+                let cost = posting.cost.as_ref()
+                    .map(|c| c.per_unit(amount.price))
+                    .or_else(|| posting.lot.as_ref().and_then(|lot| lot.cost.clone()));
+                if let Some(cost) = cost {
+                    self.prices.add(tx.header.date, amount.unit, &cost);
+                }
+            }
+        }
+
+        let result = self.running.apply(&tx)
+            .map_err(|error| JournalError::Assertion { location: location.clone(), error });
+        self.entries.push(Entry {
+            transaction: tx,
+            location,
+            posting_ids,
+        });
+
+        result
+    }
+
+    /// Returns the transactions forecast by the periodic transactions from
+    /// `begin` up to but not including `end`, sorted by date. The location
+    /// of an entry is the one of its periodic transaction.
+    ///
+    /// Periods are resolved against `today` with fiscal years starting in
+    /// the month `fiscal_year_start`. The transactions are balanced, but not
+    /// added to the journal.
+    pub fn forecast(&self, begin: NaiveDate, end: NaiveDate, today: NaiveDate, fiscal_year_start: u32) -> Result<Vec<Entry<'a>>, Vec<JournalError<'a>>> {
+        let mut errors = Vec::new();
+        let mut entries = Vec::new();
+
+        for (periodic, location) in self.periodic.iter() {
+            let period = match Period::parse_with(&periodic.period, today, fiscal_year_start) {
+                Some(period) => period,
+                None => {
+                    errors.push(JournalError::Period {
+                        location: location.clone(),
+                        period: periodic.period.clone(),
+                    });
+                    continue;
+                },
+            };
+
+            for mut tx in forecast(periodic, &period, begin, end) {
+                let ids = balance_transaction_with(&mut tx, &self.precisions)
+                    .map_err(|error| JournalError::Balance { location: location.clone(), error })
+                    .and_then(|()| {
+                        self.find_ids(&tx)
+                            .map_err(|name| JournalError::UnknownName { location: location.clone(), name })
+                    });
+                match ids {
+                    Ok(posting_ids) => entries.push(Entry {
+                        transaction: tx,
+                        location: location.clone(),
+                        posting_ids,
+                    }),
+                    Err(error) => {
+                        errors.push(error);
+                        break;
+                    },
+                }
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        entries.sort_by_key(|entry| entry.transaction.header.date);
+        Ok(entries)
+    }
+
+    /// Adds the transactions forecast by the periodic transactions like
+    /// `forecast` to the journal.
+    ///
+    /// The problems found are returned.
+    pub fn add_forecast(&mut self, begin: NaiveDate, end: NaiveDate, today: NaiveDate, fiscal_year_start: u32) -> Vec<JournalError<'a>> {
+        let entries = match self.forecast(begin, end, today, fiscal_year_start) {
+            Ok(entries) => entries,
+            Err(errors) => return errors,
+        };
+
+        entries.into_iter()
+            .filter_map(|entry| self.add_transaction(entry.transaction, entry.location).err())
+            .collect()
+    }
+
+    /// Returns the ids of the postings of a transaction which is not added,
+    /// like a forecast one, or the first name in it which is not numbered.
+    pub fn find_ids(&self, tx: &Transaction<'a>) -> Result<Vec<PostingIds>, &'a str> {
+        tx.posting.iter()
+            .map(|posting| {
+                let unit = |amount: Option<&Amount<'a>>| match amount {
+                    Some(a) => self.commodity_table.get(a.unit).map(Some).ok_or(a.unit),
+                    None => Ok(None),
+                };
+                Ok(PostingIds {
+                    account: self.account_table.get(posting.account).ok_or(posting.account)?,
+                    commodity: unit(posting.amount.as_ref())?,
+                    cost: unit(cost_amount(posting))?,
+                })
+            })
+            .collect()
+    }
+
+    // Numbers the names in a posting. Amounts without a commodity are
+    // numbered by the empty unit.
+    fn intern_posting(&mut self, posting: &Posting<'a>) -> PostingIds {
+        for unit in units(posting) {
+            self.commodity_table.intern(unit);
+        }
+
+        let commodities = &mut self.commodity_table;
+        let mut unit = |amount: Option<&Amount<'a>>| amount.map(|a| commodities.intern(a.unit));
+        PostingIds {
+            account: self.account_table.intern(posting.account),
+            commodity: unit(posting.amount.as_ref()),
+            cost: unit(cost_amount(posting)),
+        }
+    }
+
+    fn check_declared(&self, tx: &Transaction<'a>, location: &Location) -> Result<(), JournalError<'a>> {
+        for posting in tx.posting.iter() {
+            let declared = self.account_table.get(posting.account)
+                .is_some_and(|id| self.declared_accounts.contains(&id));
+            if !declared {
+                return Err(JournalError::UndeclaredAccount {
+                    location: location.clone(),
+                    account: posting.account,
+                });
+            }
+
+            for unit in units(posting) {
+                let declared = self.commodity_table.get(unit)
+                    .is_some_and(|id| self.declared_commodities.contains(&id));
+                if !declared {
+                    return Err(JournalError::UndeclaredCommodity {
+                        location: location.clone(),
+                        unit,
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Returns the lot cost or the price of a posting.
+fn cost_amount<'p, 'a>(posting: &'p Posting<'a>) -> Option<&'p Amount<'a>> {
+    posting.lot.as_ref()
+        .and_then(|lot| lot.cost.as_ref())
+        .or_else(|| posting.cost.as_ref().map(|c| c.amount()))
+}
+
+// Iterates over the commodities of the amounts in a posting.
+fn units<'p, 'a>(posting: &'p Posting<'a>) -> impl Iterator<Item = &'a str> + 'p {
+    posting.amount.iter()
+        .chain(posting.assign.iter())
+        .chain(posting.cost.iter().map(|c| c.amount()))
+        .chain(posting.lot.iter().flat_map(|lot| lot.cost.iter()))
+        .map(|a| a.unit)
+        .filter(|unit| !unit.is_empty())
+}
+
+impl Journal<'static> {
+    /// Parses a journal like `parse`, but without borrowing `src` so that the
+    /// journal outlives it.
+    ///
+    /// Account names, aliases and commodities are interned and never freed,
+    /// which leaks memory proportional to the number of distinct names. The
+    /// other text is owned by the journal.
+    pub fn parse_owned(src: &str, filename: &str) -> Result<Self, Vec<JournalError<'static>>> {
+        let mut journal = Self::new();
+        let errors = journal.read_owned(src, filename);
+
+        if errors.is_empty() {
+            Ok(journal)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Reads the items of a journal text like `read`, but without borrowing
+    /// `src`. Names are interned as in `parse_owned`.
+    ///
+    /// Texts of different lifetimes can be read into the same journal, which
+    /// can be kept, mutated and sent to other threads after they are dropped.
+    pub fn read_owned(&mut self, src: &str, filename: &str) -> Vec<JournalError<'static>> {
+        self.read_with(src, filename, LedgerItem::into_owned)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn report_every_problem() {
+        let src = r#"2021-09-16 * 引き出し
+    資産:現金           1000 JPY = 1000 JPY
+    資産:普通預金:JP
+
+2021-09-17 * 引き出し
+    資産:現金           1000 JPY
+    資産:普通預金:JP    -900 JPY
+
+2021-09-18 * 引き出し
+    資産:現金           1000 JPY = 1500 JPY
+    資産:普通預金:JP
+"#;
+        let errors = Journal::parse(src, "a.ledger").unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+
+        assert_eq!(
+            messages,
+            vec![
+                "a.ledger:5: Transaction does not balance: residual 100 JPY",
+                "a.ledger:9: Balance assertion failed on 資産:現金: expected 1500 JPY, but got 2000 JPY",
+            ]
+        );
+    }
+
+    const DECLARED: &str = r#"account 資産:現金
+account 資産:普通預金:JP
+    alias 普通預金
+commodity JPY
+
+2021-09-16 * 引き出し
+    資産:現金           1000 JPY
+    普通預金
+
+2021-09-17 * 引き出し
+    資産:現金           1000 JPY
+    資産:普通預金
+
+2021-09-18 * 両替
+    資産:現金           10 USD @ 110 JPY
+    資産:普通預金:JP    -1100 JPY
+"#;
+
+    #[test]
+    fn resolve_account_alias() {
+        let journal = Journal::parse(DECLARED, "a.ledger").unwrap();
+
+        assert_eq!(journal.accounts.len(), 2);
+        assert_eq!(journal.commodities.len(), 1);
+        assert_eq!(journal.entries[0].transaction.posting[1].account, "資産:普通預金:JP");
+    }
+
+    #[test]
+    fn number_accounts_and_commodities() {
+        let journal = Journal::parse(DECLARED, "a.ledger").unwrap();
+        let accounts = &journal.account_table;
+        let jp = accounts.get("資産:普通預金:JP").unwrap();
+
+        assert_eq!(accounts.len(), 4);
+        assert_eq!(accounts.parent(jp), accounts.get("資産:普通預金"));
+        assert_eq!(accounts.children(accounts.get("資産").unwrap()).len(), 2);
+        assert_eq!(journal.commodity_table.iter().map(|(_, unit)| unit).collect::<Vec<_>>(), vec!["JPY", "USD"]);
+
+        let unit = |id: Option<CommodityId>| id.map(|id| journal.commodity_table.unit(id));
+        let entry = &journal.entries[2];
+        let ids = entry.posting_ids[0];
+        assert_eq!(accounts.name(ids.account), "資産:現金");
+        assert_eq!((unit(ids.commodity), unit(ids.cost)), (Some("USD"), Some("JPY")));
+        assert_eq!(journal.find_ids(&entry.transaction), Ok(entry.posting_ids.clone()));
+    }
+
+    #[test]
+    fn reject_undeclared_names_in_strict_mode() {
+        let mut journal = Journal::with_options(JournalOptions { strict: true });
+        let errors = journal.read(DECLARED, "a.ledger");
+
+        assert_eq!(
+            errors,
+            vec![
+                JournalError::UndeclaredAccount {
+                    location: Location { filename: "a.ledger".to_owned(), line: 10 },
+                    account: "資産:普通預金",
+                },
+                JournalError::UndeclaredCommodity {
+                    location: Location { filename: "a.ledger".to_owned(), line: 14 },
+                    unit: "USD",
+                },
+            ]
+        );
+        assert_eq!(journal.entries.len(), 1);
+    }
+
+    #[test]
+    fn balance_at_declared_precision() {
+        let tx = "2021-09-18 * 両替\n    資産:現金  0.5 USD @ 111.5 JPY\n    資産:普通預金:JP  -56 JPY\n";
+        let declared = format!("commodity JPY\n    format 1,000 JPY\n\n{}", tx);
+
+        assert!(Journal::parse(&declared, "a.ledger").is_ok());
+        assert!(Journal::parse(tx, "a.ledger").is_err());
+    }
+
+    #[test]
+    fn apply_automated_transactions() {
+        let src = r#"2021-09-16 * 引き出し
+    費用:食費           300 JPY
+    資産:現金
+
+= /^費用:食費/
+    [予算:食費]         -1
+    [予算:残高]         1
+
+2021-09-20 * Tomod's
+    費用:食費           500 JPY
+    資産:現金
+"#;
+        let journal = Journal::parse(src, "a.ledger").unwrap();
+        let accounts = |i: usize| journal.entries[i].transaction.posting.iter()
+            .map(|p| format!("{} {}", p.account, p.amount.as_ref().unwrap()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(accounts(0).len(), 2);
+        assert_eq!(
+            accounts(1),
+            vec!["費用:食費 500 JPY", "資産:現金 -500 JPY", "予算:食費 -500 JPY", "予算:残高 500 JPY"]
+        );
+
+        let unbalanced = src.replace("[予算:残高]         1", "[予算:残高]         2");
+        let errors = Journal::parse(&unbalanced, "a.ledger").unwrap_err();
+        assert_eq!(errors[0].to_string(), "a.ledger:9: Transaction does not balance: residual 500 JPY");
+    }
+
+    #[test]
+    fn add_forecast_transactions() {
+        let src = r#"~ monthly from 2021-04  家賃
+    費用:家賃           80000 JPY
+    資産:普通預金
+
+2021-09-25 * 給与
+    資産:普通預金       300000 JPY
+    収益:給与
+"#;
+        let mut journal = Journal::parse(src, "a.ledger").unwrap();
+        let ymd = |m, d| chrono::NaiveDate::from_ymd_opt(2021, m, d).unwrap();
+        let errors = journal.add_forecast(ymd(9, 26), ymd(12, 1), ymd(9, 26), 1);
+
+        assert!(errors.is_empty());
+        let dates: Vec<_> = journal.entries.iter().map(|e| e.transaction.header.date.to_string()).collect();
+        assert_eq!(dates, vec!["2021-09-25", "2021-10-01", "2021-11-01"]);
+        assert_eq!(journal.entries[1].location.line, 1);
+
+        journal.periodic[0].0.posting[0].account = "費用:食費";
+        let errors = journal.forecast(ymd(9, 26), ymd(12, 1), ymd(9, 26), 1).unwrap_err();
+        assert_eq!(errors[0].to_string(), "a.ledger:1: Unknown account or commodity 費用:食費");
+
+        journal.periodic[0].0.period = "every fortnight".into();
+        let errors = journal.add_forecast(ymd(9, 26), ymd(12, 1), ymd(9, 26), 1);
+        assert_eq!(errors[0].to_string(), "a.ledger:1: Invalid period every fortnight");
+
+        let invalid = src.replace("monthly from 2021-04", "every fortnight");
+        let errors = Journal::parse(&invalid, "a.ledger").unwrap_err();
+        assert_eq!(errors[0].to_string(), "a.ledger:1: Invalid period every fortnight");
+    }
+
+    #[test]
+    fn keep_owned_journal_after_sources_are_dropped() {
+        let mut journal = Journal::new();
+        for (i, src) in DECLARED.split("\n\n").enumerate() {
+            let src = src.to_owned();
+            assert!(journal.read_owned(&src, &format!("{}.ledger", i)).is_empty());
+        }
+
+        let journal = std::thread::spawn(move || {
+            journal.entries.retain(|e| e.transaction.header.date.to_string() != "2021-09-17");
+            journal
+        }).join().unwrap();
+
+        assert_eq!(journal.accounts.len(), 2);
+        assert_eq!(journal.entries.len(), 2);
+        assert_eq!(journal.entries[1].location.filename, "3.ledger");
+        let tx = &journal.entries[0].transaction;
+        assert_eq!(tx.posting[1].account, "資産:普通預金:JP");
+        assert!(std::ptr::eq(tx.posting[0].account, journal.accounts[0].name));
+        assert!(matches!(tx.header.description, Cow::Owned(_)));
+    }
+
+    #[test]
+    fn report_unresolved_include() {
+        let errors = Journal::parse("include b.ledger\n", "a.ledger").unwrap_err();
+
+        assert_eq!(
+            errors,
+            vec![JournalError::UnresolvedInclude {
+                location: Location { filename: "a.ledger".to_owned(), line: 1 },
+                path: "b.ledger".into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn collect_prices() {
+        let src = format!("{}\nP 2021-09-17 USD 112 JPY\n", DECLARED);
+        let journal = Journal::parse(&src, "a.ledger").unwrap();
+        let ymd = |d| chrono::NaiveDate::from_ymd_opt(2021, 9, d).unwrap();
+
+        assert_eq!(journal.prices.rate("USD", "JPY", ymd(17)), Some(112.into()));
+        assert_eq!(journal.prices.rate("USD", "JPY", ymd(18)), Some(110.into()));
+    }
+}
@@ -1 +1,13 @@
+pub mod assertion;
+pub mod automation;
+pub mod balancer;
+pub mod forecast;
+pub mod intern;
+pub mod inventory;
+pub mod journal;
+pub mod loader;
 pub mod parser;
+pub mod period;
+pub mod price;
+pub mod query;
+pub mod report;
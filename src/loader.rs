@@ -0,0 +1,363 @@
+use crate::journal::{Journal, JournalError, Location};
+use crate::parser::directive::include_directive;
+use crate::parser::{LedgerError, LedgerItem, LedgerParser};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum LoadError {
+    #[error("{}: {error}", .path.display())]
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    #[error("{location}: Invalid pattern {pattern}: {error}")]
+    Pattern {
+        location: Location,
+        pattern: String,
+        error: glob::PatternError,
+    },
+    #[error("{location}: No file matches {pattern}")]
+    NoMatch {
+        location: Location,
+        pattern: String,
+    },
+    #[error("{location}: {} is already included", .path.display())]
+    Duplicate {
+        location: Location,
+        path: PathBuf,
+    },
+    #[error("{location}: Include cycle: {}", format_cycle(.cycle))]
+    Cycle {
+        location: Location,
+        cycle: Vec<PathBuf>,
+    },
+}
+
+fn format_cycle(cycle: &[PathBuf]) -> String {
+    cycle.iter()
+        .map(|p| p.display().to_string())
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+/// A journal file read by a loader.
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub content: String,
+    // Files included by the `include` directive on each line
+    includes: HashMap<usize, Vec<usize>>,
+}
+
+/// Reads a journal file together with the files it includes.
+///
+/// Every file is read before parsing, so that the items parsed from them
+/// can borrow the contents for as long as the loader lives. The path of an
+/// `include` directive is relative to the including file and may be a glob
+/// pattern like `2021/*.ledger`, which skips the including file itself. A
+/// file may be included only once.
+#[derive(Debug, Clone, Default)]
+pub struct Loader {
+    files: Vec<SourceFile>,
+    index: HashMap<PathBuf, usize>,
+}
+
+impl Loader {
+    /// Reads the file at `path` and all the files included from it.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, LoadError> {
+        let mut loader = Self::default();
+        loader.load_file(path.as_ref(), &mut Vec::new(), None)?;
+        Ok(loader)
+    }
+
+    pub fn files(&self) -> &[SourceFile] {
+        &self.files
+    }
+
+    fn load_file(&mut self, path: &Path, stack: &mut Vec<PathBuf>, from: Option<&Location>) -> Result<usize, LoadError> {
+        let io_error = |error| LoadError::Io { path: path.to_owned(), error };
+        let canonical = path.canonicalize().map_err(io_error)?;
+
+        // The stack is empty for the root file, so `from` is always given here
+        if let Some(pos) = stack.iter().position(|p| p == &canonical) {
+            let mut cycle = stack[pos..].to_vec();
+            cycle.push(canonical);
+            return Err(LoadError::Cycle {
+                location: from.cloned().unwrap(),
+                cycle,
+            });
+        }
+        if self.index.contains_key(&canonical) {
+            return Err(LoadError::Duplicate {
+                location: from.cloned().unwrap(),
+                path: path.to_owned(),
+            });
+        }
+
+        let content = std::fs::read_to_string(path).map_err(io_error)?;
+        let directives: Vec<_> = content.lines()
+            .enumerate()
+            .filter_map(|(i, line)| {
+                let (_, target) = include_directive(line).ok()?;
+                Some((i + 1, target.to_owned()))
+            })
+            .collect();
+
+        let i = self.files.len();
+        self.files.push(SourceFile {
+            path: path.to_owned(),
+            content,
+            includes: HashMap::new(),
+        });
+        self.index.insert(canonical.clone(), i);
+
+        stack.push(canonical);
+        for (line, target) in directives {
+            let location = Location {
+                filename: path.to_string_lossy().into_owned(),
+                line,
+            };
+            let mut included = Vec::new();
+            for target in resolve(path, &target, &location)? {
+                included.push(self.load_file(&target, stack, Some(&location))?);
+            }
+            self.files[i].includes.insert(line, included);
+        }
+        stack.pop();
+
+        Ok(i)
+    }
+
+    /// Visits the items of the loaded files in order.
+    ///
+    /// The items of an included file are visited in place of the `include`
+    /// directive. Broken items are reported as errors and skipped.
+    pub fn walk<'a, F>(&'a self, mut f: F)
+    where
+        F: FnMut(Result<LedgerItem<'a>, LedgerError>, Location),
+    {
+        if !self.files.is_empty() {
+            self.walk_file(0, &mut f);
+        }
+    }
+
+    fn walk_file<'a, F>(&'a self, i: usize, f: &mut F)
+    where
+        F: FnMut(Result<LedgerItem<'a>, LedgerError>, Location),
+    {
+        let file = &self.files[i];
+        let filename = file.path.to_string_lossy();
+        let mut parser = LedgerParser::with_filename(&file.content, &filename).recovering();
+
+        loop {
+            let location = Location {
+                filename: filename.clone().into_owned(),
+                line: parser.line(),
+            };
+
+            match parser.next() {
+                None => break,
+                Some(Ok(LedgerItem::Include(path))) => match file.includes.get(&location.line) {
+                    Some(included) => {
+                        for &j in included.iter() {
+                            self.walk_file(j, f);
+                        }
+                    },
+                    // Left to the journal, which reports it as unresolved
+                    None => f(Ok(LedgerItem::Include(path)), location),
+                },
+                Some(item) => f(item, location),
+            }
+        }
+    }
+
+    /// Reads the loaded files into `journal` and returns the problems found.
+    pub fn read_into<'a>(&'a self, journal: &mut Journal<'a>) -> Vec<JournalError<'a>> {
+        self.read_with(journal, |item| item)
+    }
+
+    /// Reads the loaded files into `journal` without borrowing them, so that
+    /// the journal outlives the loader.
+    ///
+    /// Account names, aliases and commodities are interned and never freed,
+    /// as in `Journal::parse_owned`.
+    pub fn read_into_owned(&self, journal: &mut Journal<'static>) -> Vec<JournalError<'static>> {
+        self.read_with(journal, LedgerItem::into_owned)
+    }
+
+    fn read_with<'s, 'a, F>(&'s self, journal: &mut Journal<'a>, convert: F) -> Vec<JournalError<'a>>
+    where
+        F: Fn(LedgerItem<'s>) -> LedgerItem<'a>,
+    {
+        let mut errors = Vec::new();
+
+        self.walk(|item, location| {
+            let result = item
+                .map_err(JournalError::Parse)
+                .and_then(|item| journal.add_item(convert(item), location));
+            if let Err(e) = result {
+                errors.push(e);
+            }
+        });
+
+        errors
+    }
+}
+
+// Resolves the path of an `include` directive in the file at `path`. A
+// glob pattern does not match the file at `path`.
+fn resolve(path: &Path, target: &str, location: &Location) -> Result<Vec<PathBuf>, LoadError> {
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    let joined = base.join(target);
+
+    if !target.contains(|c| "*?[".contains(c)) {
+        return Ok(vec![joined]);
+    }
+
+    let pattern = joined.to_string_lossy().into_owned();
+    let paths: Vec<_> = glob::glob(&pattern)
+        .map_err(|error| LoadError::Pattern {
+            location: location.clone(),
+            pattern: target.to_owned(),
+            error,
+        })?
+        .filter_map(Result::ok)
+        .collect();
+
+    if paths.is_empty() {
+        return Err(LoadError::NoMatch {
+            location: location.clone(),
+            pattern: target.to_owned(),
+        });
+    }
+
+    let including = path.canonicalize().ok();
+    Ok(paths.into_iter()
+        .filter(|p| p.canonicalize().ok() != including)
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Directory with journal files for a test, which is removed when
+    // dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn join<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+            self.0.join(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    // Creates a directory of its own with journal files for a test.
+    fn setup(name: &str, files: &[(&str, &str)]) -> TempDir {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        let n = COUNT.fetch_add(1, Ordering::Relaxed);
+        let dir = TempDir(std::env::temp_dir().join(format!("mini-ledger-{}-{}-{}", name, std::process::id(), n)));
+        let _ = fs::remove_dir_all(&dir.0);
+        for (path, content) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn include_files_by_glob() {
+        let dir = setup("glob", &[
+            ("main.ledger", "account 資産:現金\ninclude 2021/*.ledger\n"),
+            ("2021/01.ledger", "2021-01-10 * 引き出し\n    資産:現金  1000 JPY\n    資産:普通預金\n"),
+            ("2021/02.ledger", "\n2021-02-10 * 引き出し\n    資産:現金  2000 JPY\n    資産:普通預金\n"),
+        ]);
+        let loader = Loader::load(dir.join("main.ledger")).unwrap();
+        let mut journal = Journal::new();
+        let errors = loader.read_into(&mut journal);
+
+        assert!(errors.is_empty());
+        assert_eq!(journal.accounts.len(), 1);
+        assert_eq!(
+            journal.entries.iter().map(|e| e.location.clone()).collect::<Vec<_>>(),
+            vec![
+                Location { filename: dir.join("2021/01.ledger").to_string_lossy().into_owned(), line: 1 },
+                Location { filename: dir.join("2021/02.ledger").to_string_lossy().into_owned(), line: 2 },
+            ]
+        );
+    }
+
+    #[test]
+    fn skip_including_file_in_glob() {
+        let dir = setup("self", &[
+            ("main.ledger", "include *.ledger\n"),
+            ("a.ledger", "2021-01-10 * 引き出し\n    資産:現金  1000 JPY\n    資産:普通預金\n"),
+        ]);
+        let loader = Loader::load(dir.join("main.ledger")).unwrap();
+
+        assert_eq!(loader.files().len(), 2);
+        assert!(loader.files()[1].path.ends_with("a.ledger"));
+    }
+
+    #[test]
+    fn detect_include_cycle() {
+        let dir = setup("cycle", &[
+            ("a.ledger", "include b.ledger\n"),
+            ("b.ledger", "\ninclude sub/c.ledger\n"),
+            ("sub/c.ledger", "include ../a.ledger\n"),
+        ]);
+        let e = Loader::load(dir.join("a.ledger")).unwrap_err();
+
+        match e {
+            LoadError::Cycle { location, cycle } => {
+                assert_eq!(location.line, 1);
+                assert!(location.filename.ends_with("c.ledger"));
+                assert_eq!(cycle.len(), 4);
+                assert_eq!(cycle[0], cycle[3]);
+            },
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn report_missing_file() {
+        let dir = setup("missing", &[
+            ("a.ledger", "include 2020/*.ledger\n"),
+        ]);
+        let e = Loader::load(dir.join("a.ledger")).unwrap_err();
+
+        assert!(matches!(e, LoadError::NoMatch { ref pattern, .. } if pattern == "2020/*.ledger"));
+    }
+
+    #[test]
+    fn reject_file_included_twice() {
+        let dir = setup("diamond", &[
+            ("main.ledger", "include a.ledger\ninclude b.ledger\n"),
+            ("a.ledger", "include common.ledger\n"),
+            ("b.ledger", "\ninclude common.ledger\n"),
+            ("common.ledger", "2021-01-10 * 引き出し\n    資産:現金  1000 JPY\n    資産:普通預金\n"),
+        ]);
+        let e = Loader::load(dir.join("main.ledger")).unwrap_err();
+
+        match e {
+            LoadError::Duplicate { location, path } => {
+                assert_eq!(location.line, 2);
+                assert!(location.filename.ends_with("b.ledger"));
+                assert!(path.ends_with("common.ledger"));
+            },
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+}
@@ -1,3 +1,355 @@
+use mini_ledger::inventory::{Booking, Inventory};
+use mini_ledger::journal::{Entry, Journal, JournalOptions};
+use mini_ledger::loader::Loader;
+use mini_ledger::parser::transaction::Transaction;
+use mini_ledger::parser::LedgerItem;
+use mini_ledger::period::{Interval, Period};
+use mini_ledger::query::Query;
+use mini_ledger::report::balance::BalanceReport;
+use mini_ledger::report::budget::BudgetReport;
+use mini_ledger::report::multi_balance::MultiBalanceReport;
+use mini_ledger::report::print::PrintReport;
+use mini_ledger::report::register::RegisterReport;
+use mini_ledger::report::{ReportOptions, ValueDate, Valuation};
+use chrono::{Months, NaiveDate};
+use std::path::PathBuf;
+use std::process;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "A small ledger-like accounting tool")]
+enum Command {
+    /// Shows the balance of each account
+    Balance {
+        #[structopt(flatten)]
+        common: CommonOpts,
+        #[structopt(flatten)]
+        value: ValueOpts,
+        #[structopt(flatten)]
+        period: PeriodOpts,
+        /// Leave out virtual postings
+        #[structopt(long)]
+        real: bool,
+        /// Query to select postings, like `acct:資産 amt:>5000 not:cur:JPY`
+        query: Vec<String>,
+    },
+    /// Lists postings with their running total
+    Register {
+        #[structopt(flatten)]
+        common: CommonOpts,
+        #[structopt(flatten)]
+        value: ValueOpts,
+        #[structopt(flatten)]
+        period: PeriodOpts,
+        /// Use effective dates of transactions
+        #[structopt(long)]
+        effective: bool,
+        /// Leave out virtual postings
+        #[structopt(long)]
+        real: bool,
+        /// Query to select postings, like `acct:資産 amt:>5000 not:cur:JPY`
+        query: Vec<String>,
+    },
+    /// Compares the totals of each month with the budget given by periodic
+    /// transactions
+    Budget {
+        #[structopt(flatten)]
+        common: CommonOpts,
+        #[structopt(flatten)]
+        value: ValueOpts,
+        #[structopt(flatten)]
+        period: PeriodOpts,
+        /// Leave out virtual postings
+        #[structopt(long)]
+        real: bool,
+        /// Query to select postings, like `acct:資産 amt:>5000 not:cur:JPY`
+        query: Vec<String>,
+    },
+    /// Prints transactions in the canonical format
+    Print {
+        #[structopt(flatten)]
+        common: CommonOpts,
+        /// Column at which amounts end [default: 52]
+        #[structopt(long)]
+        amount_column: Option<usize>,
+        /// Query to select transactions by their postings
+        query: Vec<String>,
+    },
+    /// Lists capital gains realized by selling lots
+    Gains {
+        #[structopt(flatten)]
+        common: CommonOpts,
+        /// Lots sold first without a lot annotation: fifo, lifo or average
+        #[structopt(long, default_value = "fifo", parse(try_from_str = parse_booking))]
+        booking: Booking,
+        /// Query to select postings, like `acct:資産 amt:>5000 not:cur:JPY`
+        query: Vec<String>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+struct CommonOpts {
+    /// Journal file to read
+    #[structopt(short, long, env = "LEDGER_FILE", parse(from_os_str))]
+    file: PathBuf,
+    /// Reject undeclared accounts and commodities
+    #[structopt(long)]
+    strict: bool,
+}
+
+#[derive(Debug, StructOpt)]
+struct ValueOpts {
+    /// Convert amounts to COMMODITY at market prices
+    #[structopt(short = "X", long = "value", value_name = "COMMODITY")]
+    commodity: Option<String>,
+    /// Date of the prices used by --value: transaction, end, now or YYYY-MM-DD
+    #[structopt(long, default_value = "end", parse(try_from_str = parse_value_date))]
+    value_date: ValueDate,
+}
+
+impl ValueOpts {
+    fn valuation(self) -> Option<Valuation> {
+        let date = self.value_date;
+        self.commodity.map(|commodity| Valuation { commodity, date })
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct PeriodOpts {
+    /// Period to report, like `2021Q3`, `last month` or `monthly from 2021-04`
+    #[structopt(short, long)]
+    period: Option<String>,
+    /// Report each month
+    #[structopt(short = "M", long)]
+    monthly: bool,
+    /// Report each quarter
+    #[structopt(short = "Q", long)]
+    quarterly: bool,
+    /// Report each year, which is a fiscal year if --fiscal-year-start is given
+    #[structopt(short = "Y", long)]
+    yearly: bool,
+    /// Month in which fiscal years start, like 4 for April
+    #[structopt(long, env = "LEDGER_FISCAL_YEAR_START", value_name = "MONTH", default_value = "1",
+                parse(try_from_str = parse_month))]
+    fiscal_year_start: u32,
+    /// Add transactions forecast by periodic transactions after the last
+    /// transaction, up to the end of the period or six months from today
+    #[structopt(long)]
+    forecast: bool,
+}
+
+impl PeriodOpts {
+    fn period(&self) -> Period {
+        let today = chrono::Local::now().date_naive();
+        let mut period = match &self.period {
+            Some(s) => Period::parse_with(s, today, self.fiscal_year_start).unwrap_or_else(|| exit_with(&format!("Invalid period {}", s))),
+            None => Period::default(),
+        };
+
+        if self.yearly && self.fiscal_year_start != 1 {
+            period.interval = Some(Interval::FiscalYearly(self.fiscal_year_start));
+        } else if self.yearly {
+            period.interval = Some(Interval::Yearly);
+        } else if self.quarterly {
+            period.interval = Some(Interval::Quarterly);
+        } else if self.monthly {
+            period.interval = Some(Interval::Monthly);
+        }
+
+        period
+    }
+
+    // Adds the forecast transactions to `journal` if requested.
+    fn add_forecast(&self, journal: &mut Journal, period: &Period) {
+        if !self.forecast {
+            return;
+        }
+
+        let today = chrono::Local::now().date_naive();
+        let begin = journal.entries.iter()
+            .map(|entry| entry.transaction.header.date)
+            .max()
+            .and_then(|date| date.succ_opt())
+            .unwrap_or(today);
+        let end = period.span.end.unwrap_or_else(|| today + Months::new(6));
+
+        let errors = journal.add_forecast(begin, end, today, self.fiscal_year_start);
+        if !errors.is_empty() {
+            exit_with_errors(&errors);
+        }
+    }
+
+    // Returns the transactions forecast over the period, whose open sides
+    // are bounded by the intervals of the transactions in `journal`.
+    fn budget<'a>(&self, journal: &Journal<'a>, period: &Period) -> Vec<Entry<'a>> {
+        let today = chrono::Local::now().date_naive();
+        let interval = period.interval.unwrap_or(Interval::Monthly);
+        let dates = journal.entries.iter().map(|entry| entry.transaction.header.date);
+        let begin = period.span.begin.or_else(|| dates.clone().min().map(|date| interval.start(date)));
+        let end = period.span.end.or_else(|| dates.max().map(|date| interval.next(date)));
+
+        match (begin, end) {
+            (Some(begin), Some(end)) => journal.forecast(begin, end, today, self.fiscal_year_start)
+                .unwrap_or_else(|errors| exit_with_errors(&errors)),
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn parse_month(s: &str) -> Result<u32, String> {
+    match s.parse() {
+        Ok(month) if (1..=12).contains(&month) => Ok(month),
+        _ => Err(format!("Invalid month {}", s)),
+    }
+}
+
+fn parse_value_date(s: &str) -> Result<ValueDate, String> {
+    match s {
+        "transaction" => Ok(ValueDate::Transaction),
+        "end" => Ok(ValueDate::End),
+        "now" => Ok(ValueDate::At(chrono::Local::now().date_naive())),
+        _ => NaiveDate::parse_from_str(s, "%Y-%m-%d")
+            .map(ValueDate::At)
+            .map_err(|_| format!("Invalid date {}", s)),
+    }
+}
+
+fn parse_booking(s: &str) -> Result<Booking, String> {
+    match s {
+        "fifo" => Ok(Booking::Fifo),
+        "lifo" => Ok(Booking::Lifo),
+        "average" => Ok(Booking::Average),
+        _ => Err(format!("Unknown booking method {}", s)),
+    }
+}
+
+fn exit_with(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1)
+}
+
+fn exit_with_errors<E: std::fmt::Display>(errors: &[E]) -> ! {
+    for e in errors.iter() {
+        eprintln!("{}", e);
+    }
+    process::exit(1)
+}
+
+fn load_files(opts: &CommonOpts) -> Loader {
+    Loader::load(&opts.file).unwrap_or_else(|e| exit_with(&e.to_string()))
+}
+
+fn load_journal<'a>(loader: &'a Loader, opts: &CommonOpts) -> Journal<'a> {
+    let mut journal = Journal::with_options(JournalOptions {
+        strict: opts.strict,
+    });
+    let errors = loader.read_into(&mut journal);
+
+    if !errors.is_empty() {
+        exit_with_errors(&errors);
+    }
+
+    journal
+}
+
+// Parses transactions as they are written, without balancing them.
+fn parse_transactions(loader: &Loader) -> Vec<Transaction<'_>> {
+    let mut transactions = Vec::new();
+
+    loader.walk(|item, _| match item {
+        Ok(LedgerItem::Transaction(tx)) => transactions.push(tx),
+        Ok(_) => {},
+        Err(e) => exit_with(&e.to_string()),
+    });
+
+    transactions
+}
+
+fn parse_query(terms: &[String]) -> Query {
+    Query::from_terms(terms).unwrap_or_else(|e| exit_with(&e.to_string()))
+}
+
 fn main() {
-    println!("Hello, world!");
+    match Command::from_args() {
+        Command::Balance { common, value, period, real, query } => {
+            let loader = load_files(&common);
+            let mut journal = load_journal(&loader, &common);
+            let options = ReportOptions {
+                query: parse_query(&query),
+                value: value.valuation(),
+                real,
+                period: period.period(),
+                ..ReportOptions::default()
+            };
+            period.add_forecast(&mut journal, &options.period);
+            if options.period.interval.is_some() {
+                print!("{}", MultiBalanceReport::new(&journal, &options));
+            } else {
+                print!("{}", BalanceReport::new(&journal, &options));
+            }
+        },
+        Command::Register { common, value, period, effective, real, query } => {
+            let loader = load_files(&common);
+            let mut journal = load_journal(&loader, &common);
+            let options = ReportOptions {
+                query: parse_query(&query),
+                effective,
+                value: value.valuation(),
+                real,
+                period: period.period(),
+            };
+            period.add_forecast(&mut journal, &options.period);
+            print!("{}", RegisterReport::new(&journal, &options));
+        },
+        Command::Budget { common, value, period, real, query } => {
+            let loader = load_files(&common);
+            let mut journal = load_journal(&loader, &common);
+            let options = ReportOptions {
+                query: parse_query(&query),
+                value: value.valuation(),
+                real,
+                period: period.period(),
+                ..ReportOptions::default()
+            };
+            let budget = period.budget(&journal, &options.period);
+            period.add_forecast(&mut journal, &options.period);
+            print!("{}", BudgetReport::new(&journal, &budget, &options));
+        },
+        Command::Print { common, amount_column, query } => {
+            let loader = load_files(&common);
+            let transactions = parse_transactions(&loader);
+            let options = ReportOptions {
+                query: parse_query(&query),
+                ..ReportOptions::default()
+            };
+            let mut report = PrintReport::new(&transactions, &options);
+            if let Some(column) = amount_column {
+                report.amount_column = column;
+            }
+            print!("{}", report);
+        },
+        Command::Gains { common, booking, query } => {
+            let loader = load_files(&common);
+            let journal = load_journal(&loader, &common);
+            let options = ReportOptions {
+                query: parse_query(&query),
+                ..ReportOptions::default()
+            };
+            let mut inventory = Inventory::new(booking);
+            for entry in journal.entries.iter() {
+                let tx = &entry.transaction;
+                let realized = inventory.realized.len();
+                if let Err(e) = inventory.add_transaction(tx) {
+                    exit_with(&format!("{}: {}", entry.location, e));
+                }
+
+                // Gains are selected by the postings which realized them
+                for r in inventory.realized[realized..].iter() {
+                    if tx.posting.iter().any(|p| p.account == r.account && options.matches_posting(tx, p)) {
+                        println!("{}", r);
+                    }
+                }
+            }
+        },
+    }
 }
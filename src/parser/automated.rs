@@ -0,0 +1,107 @@
+use nom::bytes::complete::take_while1;
+use nom::character::complete::{char, space0};
+use nom::combinator::{map, opt};
+use nom::multi::{many0, many1};
+use nom::sequence::tuple;
+use std::borrow::Cow;
+use std::fmt;
+use super::error::{require, IResult, ParseError};
+use super::transaction::{borrowed, comment, line_end, note, owned, posting, Posting};
+
+/// Automated transaction like `= /^費用:/`, whose postings are added to the
+/// transactions with a posting matching its query.
+///
+/// An amount without a commodity like `-1` or `0.1` is a multiplier of the
+/// amount of the matched posting.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutomatedTransaction<'a> {
+    /// Query selecting postings, as written
+    pub query: Cow<'a, str>,
+    pub comment: Option<Cow<'a, str>>,
+    /// Comment lines below the query, as written after `;`
+    pub notes: Vec<Cow<'a, str>>,
+    pub posting: Vec<Posting<'a>>,
+}
+
+impl<'a> AutomatedTransaction<'a> {
+    /// Returns the automated transaction without borrowing the source text.
+    pub fn into_owned(self) -> AutomatedTransaction<'static> {
+        AutomatedTransaction {
+            query: owned(self.query),
+            comment: self.comment.map(owned),
+            notes: self.notes.into_iter().map(owned).collect(),
+            posting: self.posting.into_iter().map(Posting::into_owned).collect(),
+        }
+    }
+}
+
+impl<'a> fmt::Display for AutomatedTransaction<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let column = f.width().unwrap_or(super::transaction::AMOUNT_COLUMN);
+
+        write!(f, "= {}", self.query)?;
+        if let Some(comment) = &self.comment {
+            write!(f, " ; {}", comment)?;
+        }
+        writeln!(f)?;
+        for note in self.notes.iter() {
+            writeln!(f, "    ;{}", note)?;
+        }
+        for posting in self.posting.iter() {
+            writeln!(f, "{:column$}", posting, column = column)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses an automated transaction.
+pub fn automated_transaction(input: &str) -> IResult<&str, AutomatedTransaction<'_>> {
+    map(
+        tuple((
+            char('='),
+            space0,
+            require(ParseError::MissingQuery, take_while1(|c: char| c != ';' && c != '\n')),
+            opt(comment),
+            line_end,
+            many0(note),
+            require(ParseError::MissingPosting, many1(posting)),
+        )),
+        |(_, _, query, comment, _, notes, posting)| AutomatedTransaction {
+            query: Cow::Borrowed(query.trim_end()),
+            comment: comment.map(Cow::Borrowed),
+            notes: borrowed(notes),
+            posting,
+        }
+    )(input)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::transaction::Amount;
+
+    #[test]
+    fn parse_automated_transaction() {
+        let (rest, auto) = automated_transaction("= /^費用:/ ; 予算\n    (予算:食費)  -1\n    (予算:残高)  500 JPY\n\n").unwrap();
+
+        assert_eq!(rest, "\n");
+        assert_eq!(auto.query, "/^費用:/");
+        assert_eq!(auto.comment.as_deref(), Some("予算"));
+        assert_eq!(auto.posting[0].amount, Amount::from_str("-1", "").ok());
+        assert_eq!(auto.posting[1].amount, Amount::from_str("500", "JPY").ok());
+        assert_eq!(
+            auto.to_string(),
+            "= /^費用:/ ; 予算\n    (予算:食費)                                   -1\n    (予算:残高)                              500 JPY\n"
+        );
+    }
+
+    #[test]
+    fn reject_automated_transaction_without_query() {
+        let e = automated_transaction("=\n    (予算:食費)  -1\n").unwrap_err();
+        assert!(matches!(e, nom::Err::Failure(crate::parser::error::Error { kind: ParseError::MissingQuery, .. })));
+
+        let e = automated_transaction("= 食費\n\n").unwrap_err();
+        assert!(matches!(e, nom::Err::Failure(crate::parser::error::Error { kind: ParseError::MissingPosting, .. })));
+    }
+}
@@ -0,0 +1,329 @@
+use chrono::NaiveDate;
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take_while1};
+use nom::character::complete::{char, satisfy, space0, space1};
+use nom::combinator::{map, opt, peek, value};
+use nom::multi::many0;
+use nom::sequence::{preceded, terminated, tuple};
+use std::borrow::Cow;
+use crate::intern::intern;
+use super::error::{require, IResult, ParseError};
+use super::transaction::{account, amount_unit, comment, date, line_end, owned, posting_indent, unit, Amount};
+
+/// Declaration of an account by the `account` directive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountDeclaration<'a> {
+    pub name: &'a str,
+    pub aliases: Vec<&'a str>,
+    pub note: Option<Cow<'a, str>>,
+    /// Value expression given by `assert`, which is kept but not evaluated
+    pub assert: Option<Cow<'a, str>>,
+}
+
+/// Declaration of a commodity by the `commodity` directive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommodityDeclaration<'a> {
+    pub unit: &'a str,
+    /// Sample amount given by `format` like `1,000.00 JPY`
+    pub format: Option<Cow<'a, str>>,
+    pub note: Option<Cow<'a, str>>,
+    /// True if the commodity is the default one
+    pub default: bool,
+}
+
+/// Market price of a commodity given by the `P` directive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceDirective<'a> {
+    pub date: NaiveDate,
+    pub unit: &'a str,
+    /// Price of one unit of the commodity
+    pub price: Amount<'a>,
+}
+
+impl<'a> AccountDeclaration<'a> {
+    /// Returns the declaration with its account and aliases interned and its
+    /// text owned.
+    pub fn into_owned(self) -> AccountDeclaration<'static> {
+        AccountDeclaration {
+            name: intern(self.name),
+            aliases: self.aliases.into_iter().map(intern).collect(),
+            note: self.note.map(owned),
+            assert: self.assert.map(owned),
+        }
+    }
+}
+
+impl<'a> CommodityDeclaration<'a> {
+    /// Returns the number of decimal places in the format, like 2 for
+    /// `1,000.00 USD`.
+    pub fn precision(&self) -> Option<u32> {
+        let format = self.format.as_ref()?;
+        let number: String = format.chars()
+            .skip_while(|c| !c.is_ascii_digit())
+            .take_while(|c| c.is_ascii_digit() || *c == ',' || *c == '.')
+            .collect();
+
+        Some(number.rfind('.').map_or(0, |i| (number.len() - i - 1) as u32))
+    }
+
+    /// Returns the declaration with its commodity interned and its text owned.
+    pub fn into_owned(self) -> CommodityDeclaration<'static> {
+        CommodityDeclaration {
+            unit: intern(self.unit),
+            format: self.format.map(owned),
+            note: self.note.map(owned),
+            default: self.default,
+        }
+    }
+}
+
+impl<'a> PriceDirective<'a> {
+    /// Returns the price with its commodities interned.
+    pub fn into_owned(self) -> PriceDirective<'static> {
+        PriceDirective {
+            date: self.date,
+            unit: intern(self.unit),
+            price: self.price.into_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum AccountSub<'a> {
+    Alias(&'a str),
+    Note(&'a str),
+    Assert(&'a str),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum CommoditySub<'a> {
+    Format(&'a str),
+    Note(&'a str),
+    Default,
+}
+
+// Parses the rest of a line without trailing spaces
+fn rest_of_line(input: &str) -> IResult<&str, &str> {
+    map(
+        take_while1(|c| c != '\n' && c != '\r'),
+        str::trim_end
+    )(input)
+}
+
+// Parses a keyword followed by its argument
+fn keyword_arg<'a>(keyword: &'static str) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str> {
+    preceded(tuple((tag(keyword), space1)), rest_of_line)
+}
+
+// Parses an indented line of a directive.
+//
+// Lines with only spaces are not sub-directives.
+fn sub_directive<'a, O, F>(f: F) -> impl FnMut(&'a str) -> IResult<&'a str, O>
+where
+    F: FnMut(&'a str) -> IResult<&'a str, O>,
+{
+    preceded(
+        tuple((posting_indent, peek(satisfy(|c| !c.is_whitespace())))),
+        terminated(require(ParseError::UnknownDirective, f), line_end)
+    )
+}
+
+fn account_sub(input: &str) -> IResult<&str, AccountSub<'_>> {
+    alt((
+        map(keyword_arg("alias"), AccountSub::Alias),
+        map(keyword_arg("note"), AccountSub::Note),
+        map(keyword_arg("assert"), AccountSub::Assert),
+    ))(input)
+}
+
+fn commodity_sub(input: &str) -> IResult<&str, CommoditySub<'_>> {
+    alt((
+        map(keyword_arg("format"), CommoditySub::Format),
+        map(keyword_arg("note"), CommoditySub::Note),
+        value(CommoditySub::Default, tag("default")),
+    ))(input)
+}
+
+/// Parses an `include` directive and returns the path to include.
+pub fn include_directive(input: &str) -> IResult<&str, &str> {
+    terminated(
+        preceded(
+            tuple((tag("include"), space1)),
+            require(ParseError::MissingPath, rest_of_line)
+        ),
+        line_end
+    )(input)
+}
+
+/// Parses a market price directive like `P 2021-09-16 VTI 23000 JPY`.
+pub fn price_directive(input: &str) -> IResult<&str, PriceDirective<'_>> {
+    map(
+        tuple((
+            char('P'),
+            space1,
+            require(ParseError::DateFormat, date),
+            space1,
+            require(ParseError::MissingCommodity, unit),
+            require(ParseError::InvalidAmount, preceded(space1, amount_unit)),
+            space0,
+            opt(comment),
+            require(ParseError::InvalidAmount, line_end),
+        )),
+        |(_, _, date, _, unit, price, _, _, _)| PriceDirective {
+            date,
+            unit,
+            price,
+        }
+    )(input)
+}
+
+/// Parses an `account` directive with its sub-directives.
+pub fn account_directive(input: &str) -> IResult<&str, AccountDeclaration<'_>> {
+    map(
+        tuple((
+            tag("account"),
+            space1,
+            require(ParseError::MissingAccount, account),
+            space0,
+            opt(comment),
+            require(ParseError::UnknownDirective, line_end),
+            many0(sub_directive(account_sub)),
+        )),
+        |(_, _, name, _, _, _, subs)| {
+            let mut decl = AccountDeclaration {
+                name,
+                aliases: Vec::new(),
+                note: None,
+                assert: None,
+            };
+            for sub in subs {
+                match sub {
+                    AccountSub::Alias(alias) => decl.aliases.push(alias),
+                    AccountSub::Note(note) => decl.note = Some(Cow::Borrowed(note)),
+                    AccountSub::Assert(expr) => decl.assert = Some(Cow::Borrowed(expr)),
+                }
+            }
+            decl
+        }
+    )(input)
+}
+
+/// Parses a `commodity` directive with its sub-directives.
+pub fn commodity_directive(input: &str) -> IResult<&str, CommodityDeclaration<'_>> {
+    map(
+        tuple((
+            tag("commodity"),
+            space1,
+            require(ParseError::MissingCommodity, unit),
+            space0,
+            opt(comment),
+            require(ParseError::UnknownDirective, line_end),
+            many0(sub_directive(commodity_sub)),
+        )),
+        |(_, _, unit, _, _, _, subs)| {
+            let mut decl = CommodityDeclaration {
+                unit,
+                format: None,
+                note: None,
+                default: false,
+            };
+            for sub in subs {
+                match sub {
+                    CommoditySub::Format(format) => decl.format = Some(Cow::Borrowed(format)),
+                    CommoditySub::Note(note) => decl.note = Some(Cow::Borrowed(note)),
+                    CommoditySub::Default => decl.default = true,
+                }
+            }
+            decl
+        }
+    )(input)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_account_directive() {
+        assert_eq!(
+            account_directive("account 資産:普通預金:JP ; 銀行\n    alias 普通預金\n    note 給与の振込先\n    assert amount >= 0\n\n"),
+            Ok((
+                "\n",
+                AccountDeclaration {
+                    name: "資産:普通預金:JP",
+                    aliases: vec!["普通預金"],
+                    note: Some("給与の振込先".into()),
+                    assert: Some("amount >= 0".into()),
+                }
+            ))
+        );
+        assert_eq!(
+            account_directive("account 費用:食費"),
+            Ok((
+                "",
+                AccountDeclaration {
+                    name: "費用:食費",
+                    aliases: vec![],
+                    note: None,
+                    assert: None,
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_commodity_directive() {
+        assert_eq!(
+            commodity_directive("commodity JPY\n    format 1,000 JPY\n    note 日本円\n    default\n"),
+            Ok((
+                "",
+                CommodityDeclaration {
+                    unit: "JPY",
+                    format: Some("1,000 JPY".into()),
+                    note: Some("日本円".into()),
+                    default: true,
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn commodity_precision() {
+        let precision = |s| commodity_directive(s).unwrap().1.precision();
+
+        assert_eq!(precision("commodity JPY\n    format 1,000 JPY\n"), Some(0));
+        assert_eq!(precision("commodity USD\n    format $1,000.00\n"), Some(2));
+        assert_eq!(precision("commodity EUR\n"), None);
+    }
+
+    #[test]
+    fn parse_price_directive() {
+        assert_eq!(
+            price_directive("P 2021-09-16 VTI 23000 JPY ; close\n"),
+            Ok((
+                "",
+                PriceDirective {
+                    date: NaiveDate::from_ymd_opt(2021, 9, 16).unwrap(),
+                    unit: "VTI",
+                    price: Amount::from_str("23000", "JPY").unwrap(),
+                }
+            ))
+        );
+        assert!(price_directive("P 2021-09-16 VTI\n").is_err());
+    }
+
+    #[test]
+    fn parse_include_directive() {
+        assert_eq!(include_directive("include 2021/*.ledger  \n"), Ok(("", "2021/*.ledger")));
+        assert!(include_directive("include \n").is_err());
+    }
+
+    #[test]
+    fn parse_unknown_sub_directive() {
+        let e = commodity_directive("commodity JPY\n    nomarket\n").unwrap_err();
+        assert!(matches!(
+            e,
+            nom::Err::Failure(crate::parser::error::Error { kind: ParseError::UnknownDirective, .. })
+        ));
+    }
+}
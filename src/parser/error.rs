@@ -0,0 +1,172 @@
+use nom::error::{ErrorKind, FromExternalError};
+use nom::Parser;
+use std::fmt;
+use thiserror::Error;
+
+#[derive(Debug, Clone, Error, PartialEq)]
+pub enum ParseError {
+    #[error("Invalid date format")]
+    DateFormat,
+    #[error("Out-of-range date")]
+    DateOutOfRange,
+    #[error("Invalid beginning line")]
+    BeginningLine,
+    #[error("Unclosed code")]
+    UnclosedCode,
+    #[error("Account is missing")]
+    MissingAccount,
+    #[error("Duplicate unit")]
+    DupUnit,
+    #[error("Invalid amount")]
+    InvalidAmount,
+    #[error("Transaction has no postings")]
+    MissingPosting,
+    #[error("Unknown directive")]
+    UnknownDirective,
+    #[error("Commodity is missing")]
+    MissingCommodity,
+    #[error("Path is missing")]
+    MissingPath,
+    #[error("Query is missing")]
+    MissingQuery,
+    #[error("Period is missing")]
+    MissingPeriod,
+    #[error("Syntax error ({:?})", .0)]
+    Syntax(ErrorKind),
+}
+
+/// Error type of the nom parsers in this crate.
+///
+/// It remembers the input at which the error occurred so that the position
+/// can be recovered from the whole source text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error<I> {
+    pub input: I,
+    pub kind: ParseError,
+}
+
+pub type IResult<I, O> = nom::IResult<I, O, Error<I>>;
+
+impl<I> nom::error::ParseError<I> for Error<I> {
+    fn from_error_kind(input: I, kind: ErrorKind) -> Self {
+        Self {
+            input,
+            kind: ParseError::Syntax(kind),
+        }
+    }
+
+    fn append(_input: I, _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl<I> FromExternalError<I, ParseError> for Error<I> {
+    fn from_external_error(input: I, _kind: ErrorKind, e: ParseError) -> Self {
+        Self {
+            input,
+            kind: e,
+        }
+    }
+}
+
+impl<I> FromExternalError<I, rust_decimal::Error> for Error<I> {
+    fn from_external_error(input: I, _kind: ErrorKind, _e: rust_decimal::Error) -> Self {
+        Self {
+            input,
+            kind: ParseError::InvalidAmount,
+        }
+    }
+}
+
+/// Makes the failure of `parser` unrecoverable.
+///
+/// A plain syntax error is reported as `kind` at the beginning of the input,
+/// while errors which already carry a specific kind are kept as they are.
+pub fn require<I, O, F>(kind: ParseError, mut parser: F) -> impl FnMut(I) -> IResult<I, O>
+where
+    I: Clone,
+    F: Parser<I, O, Error<I>>,
+{
+    move |input: I| {
+        parser.parse(input.clone()).map_err(|e| match e {
+            nom::Err::Error(Error { kind: ParseError::Syntax(_), .. }) => {
+                nom::Err::Failure(Error { input: input.clone(), kind: kind.clone() })
+            },
+            nom::Err::Error(e) => nom::Err::Failure(e),
+            e => e,
+        })
+    }
+}
+
+/// Parse error located in a source file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LedgerError {
+    pub filename: String,
+    /// Line number starting from 1
+    pub line: usize,
+    /// Column number in characters starting from 1
+    pub column: usize,
+    /// The line on which the error occurred
+    pub source_line: String,
+    pub kind: ParseError,
+}
+
+impl LedgerError {
+    /// Locates an error at byte `offset` in `src`.
+    pub fn new(filename: &str, src: &str, offset: usize, kind: ParseError) -> Self {
+        let head = &src[..offset];
+        let line_start = head.rfind('\n').map_or(0, |i| i + 1);
+        let source_line = src[line_start..].lines().next().unwrap_or("");
+
+        Self {
+            filename: filename.to_owned(),
+            line: head.matches('\n').count() + 1,
+            column: head[line_start..].chars().count() + 1,
+            source_line: source_line.to_owned(),
+            kind,
+        }
+    }
+}
+
+impl fmt::Display for LedgerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let lineno = self.line.to_string();
+        let margin = " ".repeat(lineno.len());
+        let indent: String = self.source_line.chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        writeln!(f, "error: {}", self.kind)?;
+        writeln!(f, "{}--> {}:{}:{}", margin, self.filename, self.line, self.column)?;
+        writeln!(f, "{} |", margin)?;
+        writeln!(f, "{} | {}", lineno, self.source_line)?;
+        write!(f, "{} | {}^", margin, indent)
+    }
+}
+
+impl std::error::Error for LedgerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.kind)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn locate_error() {
+        let src = "2021-09-16 * foo\n    Assets 100 JPY\n2021-13-01 * bar\n";
+        let offset = src.find("2021-13").unwrap();
+        let e = LedgerError::new("a.ledger", src, offset + 5, ParseError::DateOutOfRange);
+
+        assert_eq!(e.line, 3);
+        assert_eq!(e.column, 6);
+        assert_eq!(e.source_line, "2021-13-01 * bar");
+        assert_eq!(
+            e.to_string(),
+            "error: Out-of-range date\n --> a.ledger:3:6\n  |\n3 | 2021-13-01 * bar\n  |      ^"
+        );
+    }
+}
@@ -1,55 +1,183 @@
+pub mod automated;
+pub mod directive;
+pub mod error;
+pub mod periodic;
+pub mod tag;
 pub mod transaction;
 
+pub use error::{LedgerError, ParseError};
+
+use std::borrow::Cow;
+
 use nom::{
-    IResult,
-    combinator::{opt, recognize},
+    branch::alt,
+    combinator::{eof, map, recognize},
     character::complete::{space0, line_ending},
     sequence::tuple,
 };
+use error::{require, IResult};
 
 #[derive(Debug,PartialEq)]
 pub enum LedgerItem<'a> {
     Transaction(transaction::Transaction<'a>),
+    Account(directive::AccountDeclaration<'a>),
+    Commodity(directive::CommodityDeclaration<'a>),
+    Include(Cow<'a, str>),
+    Price(directive::PriceDirective<'a>),
+    Automated(automated::AutomatedTransaction<'a>),
+    Periodic(periodic::PeriodicTransaction<'a>),
     Blank,
 }
 
+impl<'a> LedgerItem<'a> {
+    /// Returns the item without borrowing the source text, so that it can be
+    /// kept after the source is dropped.
+    pub fn into_owned(self) -> LedgerItem<'static> {
+        match self {
+            LedgerItem::Transaction(tx) => LedgerItem::Transaction(tx.into_owned()),
+            LedgerItem::Account(decl) => LedgerItem::Account(decl.into_owned()),
+            LedgerItem::Commodity(decl) => LedgerItem::Commodity(decl.into_owned()),
+            LedgerItem::Include(path) => LedgerItem::Include(transaction::owned(path)),
+            LedgerItem::Price(p) => LedgerItem::Price(p.into_owned()),
+            LedgerItem::Automated(auto) => LedgerItem::Automated(auto.into_owned()),
+            LedgerItem::Periodic(periodic) => LedgerItem::Periodic(periodic.into_owned()),
+            LedgerItem::Blank => LedgerItem::Blank,
+        }
+    }
+}
+
+/// Keywords and symbols which begin top-level directives
+const DIRECTIVES: &[&str] = &["account", "commodity", "include", "P", "=", "~"];
+
 pub struct LedgerParser<'a> {
+    filename: String,
+    src: &'a str,
     s: &'a str,
+    line: usize,
+    recover: bool,
 }
 
 impl<'a> LedgerParser<'a> {
     pub fn new(s: &'a str) -> Self {
+        Self::with_filename(s, "<input>")
+    }
+
+    /// Creates a parser whose errors refer to `filename`.
+    pub fn with_filename(s: &'a str, filename: &str) -> Self {
         Self {
-            s: s,
+            filename: filename.to_owned(),
+            src: s,
+            s,
+            line: 1,
+            recover: false,
         }
     }
+
+    /// Enables error recovery.
+    ///
+    /// When a broken item is found, the parser yields the error and resumes
+    /// at the next line which begins a new item instead of stopping.
+    pub fn recovering(mut self) -> Self {
+        self.recover = true;
+        self
+    }
+
+    /// Returns the line number at which the next item begins.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Returns the name of the file being parsed.
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    // Moves the head of the input to `rest`.
+    fn advance(&mut self, rest: &'a str) {
+        let consumed = &self.s[..self.s.len() - rest.len()];
+        self.line += consumed.matches('\n').count();
+        self.s = rest;
+    }
+
+    // Skips the item at the head of the input up to the next item.
+    fn skip_item(&mut self) {
+        let mut rest = self.s;
+
+        loop {
+            rest = rest.find('\n').map_or("", |i| &rest[i + 1..]);
+            if rest.is_empty() || is_item_start(rest) {
+                break;
+            }
+        }
+
+        self.advance(rest);
+    }
+
+    fn error(&self, e: error::Error<&'a str>) -> LedgerError {
+        let offset = self.src.len() - e.input.len();
+        LedgerError::new(&self.filename, self.src, offset, e.kind)
+    }
 }
 
 impl<'a> Iterator for LedgerParser<'a> {
-    type Item = LedgerItem<'a>;
+    type Item = Result<LedgerItem<'a>, LedgerError>;
 
-    fn next(&mut self) -> Option<LedgerItem<'a>> {
+    fn next(&mut self) -> Option<Self::Item> {
         if self.s.is_empty() {
             return None;
         }
 
-        let (remain, ret) = if self.s.starts_with(|c: char| c.is_ascii_digit()) {
-            let t = transaction::transaction(self.s).unwrap();
-            (t.0, LedgerItem::Transaction(t.1))
-        } else {
-            let (remain, _) = blank_line(self.s).unwrap();
-            (remain, LedgerItem::Blank)
-        };
+        match item(self.s) {
+            Ok((remain, item)) => {
+                self.advance(remain);
+                Some(Ok(item))
+            },
+            Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
+                let e = self.error(e);
+                if self.recover {
+                    self.skip_item();
+                } else {
+                    self.s = "";
+                }
+                Some(Err(e))
+            },
+            Err(nom::Err::Incomplete(_)) => unreachable!(),
+        }
+    }
+}
 
-        self.s = remain;
+// Returns true if the input begins with a top-level item.
+//
+// A keyword must be followed by whitespace, so that a line like `Payee`
+// does not count as a `P` directive.
+fn is_item_start(input: &str) -> bool {
+    input.starts_with(|c: char| c.is_ascii_digit())
+        || DIRECTIVES.iter().any(|d| {
+            input.strip_prefix(d)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+        })
+}
 
-        Some(ret)
+/// Parses a top-level item.
+pub fn item(input: &str) -> IResult<&str, LedgerItem<'_>> {
+    if input.starts_with(|c: char| c.is_ascii_digit()) {
+        map(transaction::transaction, LedgerItem::Transaction)(input)
+    } else {
+        alt((
+            map(directive::account_directive, LedgerItem::Account),
+            map(directive::commodity_directive, LedgerItem::Commodity),
+            map(directive::include_directive, |path| LedgerItem::Include(Cow::Borrowed(path))),
+            map(directive::price_directive, LedgerItem::Price),
+            map(automated::automated_transaction, LedgerItem::Automated),
+            map(periodic::periodic_transaction, LedgerItem::Periodic),
+            map(blank_line, |_| LedgerItem::Blank),
+        ))(input)
     }
 }
 
 pub fn blank_line(input: &str) -> IResult<&str, &str> {
     recognize(
-        tuple((space0, opt(line_ending)))
+        tuple((space0, require(ParseError::BeginningLine, alt((line_ending, eof)))))
     )(input)
 }
 
@@ -62,5 +190,18 @@ mod test {
         assert_eq!(blank_line("\n"), Ok(("", "\n")));
         assert_eq!(blank_line("  \n"), Ok(("", "  \n")));
         assert_eq!(blank_line("\t\t\n2020"), Ok(("2020", "\t\t\n")));
+        assert!(blank_line("Assets").is_err());
+    }
+
+    #[test]
+    fn start_items_at_keywords() {
+        assert!(is_item_start("2021-09-16 * 引き出し\n"));
+        assert!(is_item_start("account 資産:現金\n"));
+        assert!(is_item_start("P 2021-09-16 USD 112 JPY\n"));
+        assert!(is_item_start("= /^費用/\n"));
+        assert!(is_item_start("include"));
+        assert!(!is_item_start("accounts 資産:現金\n"));
+        assert!(!is_item_start("Payee Tomod's\n"));
+        assert!(!is_item_start("    資産:現金\n"));
     }
 }
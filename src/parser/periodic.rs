@@ -0,0 +1,141 @@
+use nom::bytes::complete::take_while;
+use nom::character::complete::{char, space0};
+use nom::combinator::{map, map_opt, opt};
+use nom::multi::{many0, many1};
+use nom::sequence::tuple;
+use std::borrow::Cow;
+use std::fmt;
+use super::error::{require, IResult, ParseError};
+use super::tag::Tags;
+use super::transaction::{borrowed, comment, line_end, note, note_tags, owned, posting, Posting};
+
+/// Periodic transaction like `~ monthly from 2021-04  家賃`, which is a
+/// template of transactions recurring in a period.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeriodicTransaction<'a> {
+    /// Period expression, as written
+    pub period: Cow<'a, str>,
+    /// Description separated from the period by two spaces or a tab
+    pub description: Cow<'a, str>,
+    pub comment: Option<Cow<'a, str>>,
+    /// Comment lines below the period, as written after `;`
+    pub notes: Vec<Cow<'a, str>>,
+    /// Tags in the comment and the notes
+    pub tags: Tags<'a>,
+    pub posting: Vec<Posting<'a>>,
+}
+
+impl<'a> PeriodicTransaction<'a> {
+    /// Returns the periodic transaction without borrowing the source text.
+    pub fn into_owned(self) -> PeriodicTransaction<'static> {
+        PeriodicTransaction {
+            period: owned(self.period),
+            description: owned(self.description),
+            comment: self.comment.map(owned),
+            notes: self.notes.into_iter().map(owned).collect(),
+            tags: self.tags.into_owned(),
+            posting: self.posting.into_iter().map(Posting::into_owned).collect(),
+        }
+    }
+}
+
+impl<'a> fmt::Display for PeriodicTransaction<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let column = f.width().unwrap_or(super::transaction::AMOUNT_COLUMN);
+
+        write!(f, "~ {}", self.period)?;
+        if !self.description.is_empty() {
+            write!(f, "  {}", self.description)?;
+        }
+        if let Some(comment) = &self.comment {
+            write!(f, " ; {}", comment)?;
+        }
+        writeln!(f)?;
+        for note in self.notes.iter() {
+            writeln!(f, "    ;{}", note)?;
+        }
+        for posting in self.posting.iter() {
+            writeln!(f, "{:column$}", posting, column = column)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Splits the header of a periodic transaction into the period and the
+// description.
+fn period_description(input: &str) -> IResult<&str, (&str, &str)> {
+    map_opt(
+        take_while(|c: char| c != ';' && c != '\n'),
+        |s: &str| {
+            let (period, description) = match s.find("  ").into_iter().chain(s.find('\t')).min() {
+                Some(i) => (&s[..i], s[i..].trim()),
+                None => (s.trim_end(), ""),
+            };
+            if period.is_empty() {
+                None
+            } else {
+                Some((period, description))
+            }
+        }
+    )(input)
+}
+
+/// Parses a periodic transaction.
+pub fn periodic_transaction(input: &str) -> IResult<&str, PeriodicTransaction<'_>> {
+    map(
+        tuple((
+            char('~'),
+            space0,
+            require(ParseError::MissingPeriod, period_description),
+            opt(comment),
+            line_end,
+            many0(note),
+            require(ParseError::MissingPosting, many1(posting)),
+        )),
+        |(_, _, (period, description), comment, _, notes, mut posting)| {
+            let tags = note_tags(comment, &notes);
+            for p in posting.iter_mut() {
+                p.tags.inherit(&tags);
+            }
+
+            PeriodicTransaction {
+                period: Cow::Borrowed(period),
+                description: Cow::Borrowed(description),
+                comment: comment.map(Cow::Borrowed),
+                notes: borrowed(notes),
+                tags,
+                posting,
+            }
+        }
+    )(input)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_periodic_transaction() {
+        let src = "~ monthly from 2021-04  家賃 ; :fixed:\n    費用:家賃  80000 JPY\n    資産:普通預金\n";
+        let (rest, periodic) = periodic_transaction(src).unwrap();
+
+        assert_eq!(rest, "");
+        assert_eq!(periodic.period, "monthly from 2021-04");
+        assert_eq!(periodic.description, "家賃");
+        assert!(periodic.posting[1].tags.contains("fixed"));
+        assert_eq!(
+            periodic.to_string(),
+            "~ monthly from 2021-04  家賃 ; :fixed:\n    費用:家賃                              80000 JPY\n    資産:普通預金\n"
+        );
+
+        let (_, periodic) = periodic_transaction("~ weekly\n    費用:食費  5000 JPY\n    資産:現金\n").unwrap();
+        assert_eq!((periodic.period.as_ref(), periodic.description.as_ref()), ("weekly", ""));
+    }
+
+    #[test]
+    fn reject_periodic_transaction_without_period() {
+        let e = periodic_transaction("~ ; 家賃\n    費用:家賃  80000 JPY\n").unwrap_err();
+        assert!(matches!(e, nom::Err::Failure(crate::parser::error::Error { kind: ParseError::MissingPeriod, .. })));
+    }
+}
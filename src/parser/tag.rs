@@ -0,0 +1,178 @@
+use chrono::NaiveDate;
+use nom::combinator::all_consuming;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use super::transaction::{amount_unit, date, Amount};
+
+/// Value of a tag given as `; Key: value`.
+///
+/// Values which look like a date or an amount are parsed as such.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TagValue<'a> {
+    Date(NaiveDate),
+    Amount(Amount<'a>),
+    Text(Cow<'a, str>),
+}
+
+impl<'a> TagValue<'a> {
+    fn parse(s: &'a str) -> Self {
+        if let Ok((_, date)) = all_consuming(date)(s) {
+            TagValue::Date(date)
+        } else if let Ok((_, amount)) = all_consuming(amount_unit)(s) {
+            TagValue::Amount(amount)
+        } else {
+            TagValue::Text(Cow::Borrowed(s))
+        }
+    }
+
+    /// Returns the value with its text owned.
+    pub fn into_owned(self) -> TagValue<'static> {
+        match self {
+            TagValue::Date(date) => TagValue::Date(date),
+            TagValue::Amount(amount) => TagValue::Amount(amount.into_owned()),
+            TagValue::Text(text) => TagValue::Text(Cow::Owned(text.into_owned())),
+        }
+    }
+}
+
+impl<'a> fmt::Display for TagValue<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TagValue::Date(date) => write!(f, "{}", date.format("%Y-%m-%d")),
+            TagValue::Amount(amount) => write!(f, "{}", amount),
+            TagValue::Text(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+/// Metadata tags written in comments.
+///
+/// A comment like `; :receipt:food:` gives tags without values, and a
+/// comment like `; project: 引っ越し` gives a tag with a value.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Tags<'a> {
+    tags: BTreeMap<Cow<'a, str>, Option<TagValue<'a>>>,
+}
+
+impl<'a> Tags<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Collects the tags in a comment.
+    pub fn parse(comment: &'a str) -> Self {
+        let mut tags = Self::new();
+        let comment = comment.trim();
+
+        if let Some((key, value)) = key_value(comment) {
+            let value = value.trim();
+            tags.insert(key, if value.is_empty() { None } else { Some(TagValue::parse(value)) });
+            return tags;
+        }
+
+        let words = comment.split_whitespace()
+            .filter(|w| w.len() > 2 && w.starts_with(':') && w.ends_with(':'));
+        for word in words {
+            for name in word[1..word.len() - 1].split(':').filter(|n| !n.is_empty()) {
+                tags.insert(name, None);
+            }
+        }
+
+        tags
+    }
+
+    pub fn insert(&mut self, name: &'a str, value: Option<TagValue<'a>>) {
+        self.tags.insert(Cow::Borrowed(name), value);
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.tags.contains_key(name)
+    }
+
+    /// Returns the value of a tag, or `None` if it has no value or is absent.
+    pub fn value(&self, name: &str) -> Option<&TagValue<'a>> {
+        self.tags.get(name).and_then(|v| v.as_ref())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty()
+    }
+
+    /// Iterates over the tags sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Option<&TagValue<'a>>)> {
+        self.tags.iter().map(|(name, value)| (name.as_ref(), value.as_ref()))
+    }
+
+    /// Adds the tags of `parent` which are not given here.
+    pub fn inherit(&mut self, parent: &Tags<'a>) {
+        for (name, value) in parent.tags.iter() {
+            self.tags.entry(name.clone()).or_insert_with(|| value.clone());
+        }
+    }
+
+    /// Returns the tags with their names and values owned.
+    pub fn into_owned(self) -> Tags<'static> {
+        Tags {
+            tags: self.tags.into_iter()
+                .map(|(name, value)| (Cow::Owned(name.into_owned()), value.map(TagValue::into_owned)))
+                .collect(),
+        }
+    }
+}
+
+// Splits a comment like `Key: value` into the key and the value.
+fn key_value(comment: &str) -> Option<(&str, &str)> {
+    let (key, value) = comment.split_once(':')?;
+
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return None;
+    }
+    if !value.is_empty() && !value.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    Some((key, value))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_tags() {
+        let tags = Tags::parse(" 洗剤 :receipt:日用品: ");
+        let names: Vec<_> = tags.iter().map(|(name, _)| name).collect();
+
+        assert_eq!(names, vec!["receipt", "日用品"]);
+        assert!(Tags::parse("Tomod's").is_empty());
+        assert!(Tags::parse("10:30 に購入").is_empty());
+    }
+
+    #[test]
+    fn parse_typed_values() {
+        let value = |s| Tags::parse(s).iter().next().and_then(|(_, v)| v.cloned());
+
+        assert_eq!(value("project: 引っ越し"), Some(TagValue::Text("引っ越し".into())));
+        assert_eq!(
+            value("date: 2021-10-27"),
+            NaiveDate::from_ymd_opt(2021, 10, 27).map(TagValue::Date)
+        );
+        assert_eq!(
+            value("limit: 5000 JPY"),
+            Amount::from_str("5000", "JPY").ok().map(TagValue::Amount)
+        );
+        assert_eq!(value("receipt:"), None);
+        assert!(Tags::parse("receipt:").contains("receipt"));
+    }
+
+    #[test]
+    fn inherit_tags() {
+        let mut tags = Tags::parse("project: 食費");
+        tags.inherit(&Tags::parse(":receipt:"));
+        tags.inherit(&Tags::parse("project: 引っ越し"));
+
+        assert!(tags.contains("receipt"));
+        assert_eq!(tags.value("project"), Some(&TagValue::Text("食費".into())));
+    }
+}
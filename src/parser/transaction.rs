@@ -1,34 +1,27 @@
 use chrono::NaiveDate;
 use nom::branch::alt;
-use nom::bytes::complete::{take_while, take_until, take_while1, tag};
-use nom::character::complete::{char, digit1, one_of, space0, space1};
-use nom::combinator::{map, map_res, opt, recognize};
-use nom::multi::{many0_count, many1};
-use nom::sequence::{preceded, tuple};
-use nom::IResult;
+use nom::bytes::complete::{take_while, take_while1, tag};
+use nom::character::complete::{char, digit1, line_ending, one_of, space0, space1};
+use nom::combinator::{eof, map, map_opt, map_res, not, opt, recognize};
+use nom::multi::{many0, many0_count, many1};
+use nom::sequence::{delimited, preceded, terminated, tuple};
 use rust_decimal::Decimal;
-use thiserror::Error;
-
-#[derive(Debug, Error, PartialEq)]
-pub enum ParseError {
-    #[error("Invalid date format")]
-    DateFormat,
-    #[error("Out-of-range date")]
-    DateOutOfRange,
-    #[error("Invalid beginning line")]
-    BeginningLine,
-    #[error("Unclosed code")]
-    UnclosedCode,
-    #[error("Account is missing")]
-    MissingAccount,
-    #[error("Duplicate unit")]
-    DupUnit,
-}
+use std::borrow::Cow;
+use std::fmt;
+use unicode_width::UnicodeWidthStr;
+use crate::intern::intern;
+use super::error::{require, IResult, ParseError};
+use super::tag::{TagValue, Tags};
+
+/// Default column at which the amounts of postings end when printed.
+///
+/// It can be changed by the width of the format, like `{:60}`.
+pub const AMOUNT_COLUMN: usize = 52;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Transaction<'a> {
-    header: TransactionHeader<'a>,
-    posting: Vec<Posting<'a>>,
+    pub header: TransactionHeader<'a>,
+    pub posting: Vec<Posting<'a>>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -40,40 +33,322 @@ pub enum Status {
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct TransactionHeader<'a> {
-    date: NaiveDate,
-    edate: Option<NaiveDate>,
-    status: Status,
-    code: Option<&'a str>,
-    description: &'a str,
-    comment: Option<&'a str>,
+    pub date: NaiveDate,
+    pub edate: Option<NaiveDate>,
+    pub status: Status,
+    pub code: Option<Cow<'a, str>>,
+    pub description: Cow<'a, str>,
+    pub comment: Option<Cow<'a, str>>,
+    /// Comment lines below the header, as written after `;`
+    pub notes: Vec<Cow<'a, str>>,
+    /// Tags in the comment and the notes
+    pub tags: Tags<'a>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Amount<'a> {
-    price: Decimal,
-    unit: &'a str,
+    pub price: Decimal,
+    pub unit: &'a str,
 }
 
 impl<'a> Amount<'a> {
+    pub fn new(price: Decimal, unit: &'a str) -> Self {
+        Self {
+            price,
+            unit,
+        }
+    }
+
     pub fn from_str(price: &'a str, unit: &'a str) -> Result<Self, rust_decimal::Error> {
         Ok(Self {
             price: price.parse()?,
-            unit: unit,
+            unit,
         })
     }
 
     pub fn dollar(price: &'a str) -> Result<Self, rust_decimal::Error> {
         Self::from_str(price, "$")
     }
+
+    /// Returns the amount with its commodity interned.
+    pub fn into_owned(self) -> Amount<'static> {
+        Amount::new(self.price, intern(self.unit))
+    }
+}
+
+impl<'a> fmt::Display for Amount<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.unit.is_empty() {
+            write!(f, "{}", self.price)
+        } else {
+            write!(f, "{} {}", self.price, self.unit)
+        }
+    }
+}
+
+/// Lot annotation of an amount like `{12300 JPY} [2021-03-01] (note)`.
+///
+/// It identifies the lot which a commodity was acquired in. Every part is
+/// optional, but at least one of them is given.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lot<'a> {
+    /// Per-unit cost at which the lot was acquired
+    pub cost: Option<Amount<'a>>,
+    /// Date on which the lot was acquired
+    pub date: Option<NaiveDate>,
+    pub note: Option<Cow<'a, str>>,
+}
+
+impl<'a> Lot<'a> {
+    /// Returns the lot with its commodity interned and its note owned.
+    pub fn into_owned(self) -> Lot<'static> {
+        Lot {
+            cost: self.cost.map(Amount::into_owned),
+            date: self.date,
+            note: self.note.map(owned),
+        }
+    }
+}
+
+impl<'a> fmt::Display for Lot<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = Vec::new();
+
+        if let Some(cost) = &self.cost {
+            parts.push(format!("{{{}}}", cost));
+        }
+        if let Some(date) = self.date {
+            parts.push(format!("[{}]", date.format("%Y-%m-%d")));
+        }
+        if let Some(note) = &self.note {
+            parts.push(format!("({})", note));
+        }
+
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+/// Cost of a posting given by `@` or `@@`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cost<'a> {
+    /// Cost of one unit like `@ 12300 JPY`
+    PerUnit(Amount<'a>),
+    /// Cost of the whole amount like `@@ 123000 JPY`
+    Total(Amount<'a>),
+}
+
+impl<'a> Cost<'a> {
+    /// Returns the amount as written.
+    pub fn amount(&self) -> &Amount<'a> {
+        match self {
+            Cost::PerUnit(amount) | Cost::Total(amount) => amount,
+        }
+    }
+
+    /// Returns the cost of one unit of `quantity`.
+    pub fn per_unit(&self, quantity: Decimal) -> Amount<'a> {
+        match self {
+            Cost::PerUnit(amount) => amount.clone(),
+            Cost::Total(amount) if quantity.is_zero() => Amount::new(Decimal::ZERO, amount.unit),
+            Cost::Total(amount) => Amount::new(amount.price / quantity.abs(), amount.unit),
+        }
+    }
+
+    /// Returns the cost of `quantity`, which has the sign of `quantity`.
+    pub fn total(&self, quantity: Decimal) -> Amount<'a> {
+        match self {
+            Cost::PerUnit(amount) => Amount::new(quantity * amount.price, amount.unit),
+            Cost::Total(amount) if quantity.is_sign_negative() => Amount::new(-amount.price, amount.unit),
+            Cost::Total(amount) => amount.clone(),
+        }
+    }
+
+    /// Returns the cost with its commodity interned.
+    pub fn into_owned(self) -> Cost<'static> {
+        match self {
+            Cost::PerUnit(amount) => Cost::PerUnit(amount.into_owned()),
+            Cost::Total(amount) => Cost::Total(amount.into_owned()),
+        }
+    }
+}
+
+impl<'a> fmt::Display for Cost<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Cost::PerUnit(amount) => write!(f, "@ {}", amount),
+            Cost::Total(amount) => write!(f, "@@ {}", amount),
+        }
+    }
+}
+
+/// Kind of a posting given by the brackets around its account.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PostingKind {
+    /// Posting to a plain account
+    Real,
+    /// Posting to `(Account)`, which does not have to balance
+    Virtual,
+    /// Posting to `[Account]`, which balances with the other postings of
+    /// the same kind
+    BalancedVirtual,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Posting<'a> {
-    account: &'a str,
-    amount: Option<Amount<'a>>,
-    assign: Option<Amount<'a>>,
-    cost: Option<Amount<'a>>,
-    comment: Option<&'a str>,
+    pub account: &'a str,
+    pub kind: PostingKind,
+    /// Date given by `; [DATE]` or a `date:` tag, which overrides the date
+    /// of the transaction
+    pub date: Option<NaiveDate>,
+    /// Effective date given by `; [=DATE]` or a `date2:` tag
+    pub edate: Option<NaiveDate>,
+    pub amount: Option<Amount<'a>>,
+    pub lot: Option<Lot<'a>>,
+    pub assign: Option<Amount<'a>>,
+    pub cost: Option<Cost<'a>>,
+    pub comment: Option<Cow<'a, str>>,
+    /// Comment lines below the posting, as written after `;`
+    pub notes: Vec<Cow<'a, str>>,
+    /// Tags in the comment and the notes, including the ones of the
+    /// transaction
+    pub tags: Tags<'a>,
+}
+
+impl<'a> Transaction<'a> {
+    /// Returns the transaction without borrowing the source text, so that it
+    /// can be kept after the source is dropped.
+    ///
+    /// Accounts and commodities are interned for the rest of the program,
+    /// and the other text is owned by the transaction.
+    pub fn into_owned(self) -> Transaction<'static> {
+        Transaction {
+            header: self.header.into_owned(),
+            posting: self.posting.into_iter().map(Posting::into_owned).collect(),
+        }
+    }
+}
+
+impl<'a> TransactionHeader<'a> {
+    /// Returns the header with its text owned.
+    pub fn into_owned(self) -> TransactionHeader<'static> {
+        TransactionHeader {
+            date: self.date,
+            edate: self.edate,
+            status: self.status,
+            code: self.code.map(owned),
+            description: owned(self.description),
+            comment: self.comment.map(owned),
+            notes: self.notes.into_iter().map(owned).collect(),
+            tags: self.tags.into_owned(),
+        }
+    }
+}
+
+impl<'a> Posting<'a> {
+    /// Returns the posting with its account interned and its text owned.
+    pub fn into_owned(self) -> Posting<'static> {
+        Posting {
+            account: intern(self.account),
+            kind: self.kind,
+            date: self.date,
+            edate: self.edate,
+            amount: self.amount.map(Amount::into_owned),
+            lot: self.lot.map(Lot::into_owned),
+            assign: self.assign.map(Amount::into_owned),
+            cost: self.cost.map(Cost::into_owned),
+            comment: self.comment.map(owned),
+            notes: self.notes.into_iter().map(owned).collect(),
+            tags: self.tags.into_owned(),
+        }
+    }
+}
+
+impl<'a> fmt::Display for Transaction<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let column = f.width().unwrap_or(AMOUNT_COLUMN);
+
+        writeln!(f, "{}", self.header)?;
+        for posting in self.posting.iter() {
+            writeln!(f, "{:column$}", posting, column = column)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Status::Cleared => write!(f, "*"),
+            Status::Pending => write!(f, "!"),
+            Status::Uncleared => Ok(()),
+        }
+    }
+}
+
+impl<'a> fmt::Display for TransactionHeader<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.date.format("%Y-%m-%d"))?;
+        if let Some(edate) = self.edate {
+            write!(f, "={}", edate.format("%Y-%m-%d"))?;
+        }
+        if self.status != Status::Uncleared {
+            write!(f, " {}", self.status)?;
+        }
+        if let Some(code) = &self.code {
+            write!(f, " ({})", code)?;
+        }
+        write!(f, " {}", self.description)?;
+        if let Some(comment) = &self.comment {
+            write!(f, "; {}", comment)?;
+        }
+        for note in self.notes.iter() {
+            write!(f, "\n    ;{}", note)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> fmt::Display for Posting<'a> {
+    /// Writes a posting, right-aligning the amount to the column given as the
+    /// width of the format. East Asian wide characters in the account name
+    /// are counted as two columns.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let column = f.width().unwrap_or(AMOUNT_COLUMN);
+        let indent = "    ";
+        let account = match self.kind {
+            PostingKind::Real => self.account.to_owned(),
+            PostingKind::Virtual => format!("({})", self.account),
+            PostingKind::BalancedVirtual => format!("[{}]", self.account),
+        };
+
+        write!(f, "{}{}", indent, account)?;
+        if let Some(amount) = &self.amount {
+            let amount = amount.to_string();
+            let used = indent.len() + account.width() + amount.width();
+            let padding = column.saturating_sub(used).max(2);
+            write!(f, "{}{}", " ".repeat(padding), amount)?;
+        }
+        if let Some(lot) = &self.lot {
+            write!(f, " {}", lot)?;
+        }
+        if let Some(assign) = &self.assign {
+            let sep = if self.amount.is_some() { " " } else { "  " };
+            write!(f, "{}= {}", sep, assign)?;
+        }
+        if let Some(cost) = &self.cost {
+            write!(f, " {}", cost)?;
+        }
+        if let Some(comment) = &self.comment {
+            write!(f, "  ; {}", comment)?;
+        }
+        for note in self.notes.iter() {
+            write!(f, "\n    ;{}", note)?;
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -97,9 +372,9 @@ impl<'a> RawDate<'a> {
     }
 
     pub fn into_naive_date(self) -> Result<NaiveDate, ParseError> {
-        let year: i32 = self.year.parse().unwrap();
-        let month: u32 = self.month.parse().unwrap();
-        let day: u32 = self.day.parse().unwrap();
+        let year: i32 = self.year.parse().map_err(|_| ParseError::DateOutOfRange)?;
+        let month: u32 = self.month.parse().map_err(|_| ParseError::DateOutOfRange)?;
+        let day: u32 = self.day.parse().map_err(|_| ParseError::DateOutOfRange)?;
 
         NaiveDate::from_ymd_opt(year, month, day)
             .ok_or(ParseError::DateOutOfRange)
@@ -123,7 +398,7 @@ fn date_dash(input: &str) -> IResult<&str, (&str, &str, &str)> {
 }
 
 /// Parses transaction date
-fn date(input: &str) -> IResult<&str, NaiveDate> {
+pub(crate) fn date(input: &str) -> IResult<&str, NaiveDate> {
     map_res(
         alt((date_slash, date_dash)),
         |t| RawDate::from_triple(t).into_naive_date()
@@ -143,45 +418,137 @@ fn status(input: &str) -> IResult<&str, Status> {
 //
 // A transaction code is a code delimited by parentheses.
 fn code(input: &str) -> IResult<&str, &str> {
-    map(
-        tuple((char('('), take_until(")"), char(')'))),
-        |(_, code, _)| code,
+    preceded(
+        char('('),
+        require(
+            ParseError::UnclosedCode,
+            terminated(take_while(|c| c != ')' && c != '\n'), char(')'))
+        )
     )(input)
 }
 
-fn comment(input: &str) -> IResult<&str, &str> {
+pub(crate) fn comment(input: &str) -> IResult<&str, &str> {
     preceded(
         tuple((char(';'), space0)),
         take_while(|c| c != '\n')
     )(input)
 }
 
-pub fn transaction_header(input: &str) -> IResult<&str, TransactionHeader> {
+// Parses an indented comment line below a header or a posting, keeping the
+// text after `;` as written
+pub(crate) fn note(input: &str) -> IResult<&str, &str> {
+    terminated(
+        preceded(
+            tuple((posting_indent, char(';'))),
+            take_while(|c| c != '\n')
+        ),
+        line_end
+    )(input)
+}
+
+// Collects the tags in the comment and the notes of a header or a posting.
+pub(crate) fn note_tags<'a>(comment: Option<&'a str>, notes: &[&'a str]) -> Tags<'a> {
+    let mut tags = Tags::new();
+    for c in comment.iter().chain(notes.iter()) {
+        tags.inherit(&Tags::parse(c));
+    }
+    tags
+}
+
+// Wraps the comment lines of a header or a posting as borrowed text.
+pub(crate) fn borrowed(notes: Vec<&str>) -> Vec<Cow<'_, str>> {
+    notes.into_iter().map(Cow::Borrowed).collect()
+}
+
+// Returns text which no longer borrows the source.
+pub(crate) fn owned(s: Cow<'_, str>) -> Cow<'static, str> {
+    Cow::Owned(s.into_owned())
+}
+
+// Parses a date annotation like `[2021-09-20=2021-10-27]` or `[=2021-10-27]`
+fn bracket_dates(input: &str) -> IResult<&str, (Option<NaiveDate>, Option<NaiveDate>)> {
+    map_opt(
+        delimited(
+            char('['),
+            tuple((opt(date), opt(preceded(char('='), date)))),
+            char(']')
+        ),
+        |(date, edate)| if date.is_none() && edate.is_none() { None } else { Some((date, edate)) }
+    )(input)
+}
+
+// Finds the date and the effective date of a posting in its comment, notes
+// and tags. Tags take precedence over the bracket syntax.
+fn posting_dates(comment: Option<&str>, notes: &[&str], tags: &Tags) -> (Option<NaiveDate>, Option<NaiveDate>) {
+    let mut dates = (None, None);
+
+    for c in comment.iter().chain(notes.iter()) {
+        for (i, _) in c.match_indices('[') {
+            if let Ok((_, (date, edate))) = bracket_dates(&c[i..]) {
+                dates.0 = date.or(dates.0);
+                dates.1 = edate.or(dates.1);
+            }
+        }
+    }
+
+    let tag_date = |name| match tags.value(name) {
+        Some(TagValue::Date(date)) => Some(*date),
+        _ => None,
+    };
+    (tag_date("date").or(dates.0), tag_date("date2").or(dates.1))
+}
+
+pub fn transaction_header(input: &str) -> IResult<&str, TransactionHeader<'_>> {
     map(
         tuple((
-            date,
-            opt(preceded(char('='), date)),
+            require(ParseError::DateFormat, date),
+            opt(preceded(char('='), require(ParseError::DateFormat, date))),
             opt(preceded(space1, status)),
             opt(preceded(space1, code)),
             space1,
             take_while(|c: char| c != ';' && c != '\n'),
             opt(comment),
-            opt(char('\n'))
+            opt(char('\n')),
+            many0(note),
         )),
-        |(date, edate, status, code, _, desc, comment, _)| TransactionHeader {
-            date: date,
-            edate: edate,
+        |(date, edate, status, code, _, desc, comment, _, notes)| TransactionHeader {
+            date,
+            edate,
             status: status.unwrap_or(Status::Uncleared),
-            code: code,
-            description: desc,
-            comment: comment,
+            code: code.map(Cow::Borrowed),
+            description: Cow::Borrowed(desc),
+            comment: comment.map(Cow::Borrowed),
+            tags: note_tags(comment, &notes),
+            notes: borrowed(notes),
         },
     )(input)
 }
 
 // Parses an account name
-fn account(input: &str) -> IResult<&str, &str> {
-    take_while1(|c: char| !c.is_ascii_whitespace())(input)
+//
+// A posting beginning with a sign or an operator lacks its account.
+pub(crate) fn account(input: &str) -> IResult<&str, &str> {
+    preceded(
+        require(ParseError::MissingAccount, not(one_of("+-=@"))),
+        take_while1(|c: char| !c.is_ascii_whitespace())
+    )(input)
+}
+
+// Parses an account name of a posting with its kind.
+fn posting_account(input: &str) -> IResult<&str, (&str, PostingKind)> {
+    let inner = |close: char| take_while1(move |c: char| c != close && !c.is_ascii_whitespace());
+
+    alt((
+        map(
+            delimited(char('('), require(ParseError::MissingAccount, inner(')')), require(ParseError::MissingAccount, char(')'))),
+            |a| (a, PostingKind::Virtual)
+        ),
+        map(
+            delimited(char('['), require(ParseError::MissingAccount, inner(']')), require(ParseError::MissingAccount, char(']'))),
+            |a| (a, PostingKind::BalancedVirtual)
+        ),
+        map(account, |a| (a, PostingKind::Real)),
+    ))(input)
 }
 
 // Parses a decimal value without sign
@@ -212,81 +579,142 @@ fn is_unit_char(c: char) -> bool {
 /// Parses a commodity unit
 /// 
 /// TODO: support quoted units
-fn unit(input: &str) -> IResult<&str, &str> {
+pub(crate) fn unit(input: &str) -> IResult<&str, &str> {
     take_while1(|c: char| is_unit_char(c))(input)
 }
 
 /// Parses amount with arbitrary unit like `1000 JPY`.
-fn amount_unit(input: &str) -> IResult<&str, Amount> {
+pub(crate) fn amount_unit(input: &str) -> IResult<&str, Amount<'_>> {
     map_res(
         tuple((decimal, opt(preceded(space1, unit)))),
         |(price, unit)| Amount::from_str(price, unit.unwrap_or(""))
     )(input)
 }
 
-fn assign_amount(input: &str) -> IResult<&str, Amount> {
+fn assign_amount(input: &str) -> IResult<&str, Amount<'_>> {
     map(
         tuple((char('='), space0, amount_unit)),
         |(_, _, amount)| amount
     )(input)
 }
 
-fn cost(input: &str) -> IResult<&str, Amount> {
-    preceded(
-        tuple((char('@'), space0)),
-        amount_unit
+// Parses a lot annotation.
+//
+// The parts must be written in the order of cost, date and note.
+fn lot(input: &str) -> IResult<&str, Lot<'_>> {
+    map_opt(
+        tuple((
+            opt(delimited(
+                tuple((char('{'), space0)),
+                require(ParseError::InvalidAmount, amount_unit),
+                require(ParseError::InvalidAmount, tuple((space0, char('}'))))
+            )),
+            opt(preceded(
+                tuple((space0, char('['))),
+                terminated(require(ParseError::DateFormat, date), require(ParseError::DateFormat, char(']')))
+            )),
+            opt(preceded(
+                tuple((space0, char('('))),
+                require(
+                    ParseError::UnclosedCode,
+                    terminated(take_while(|c| c != ')' && c != '\n'), char(')'))
+                )
+            )),
+        )),
+        |(cost, date, note)| {
+            if cost.is_none() && date.is_none() && note.is_none() {
+                None
+            } else {
+                Some(Lot { cost, date, note: note.map(Cow::Borrowed) })
+            }
+        }
     )(input)
 }
 
-fn posting_indent(input: &str) -> IResult<&str, &str> {
+fn cost(input: &str) -> IResult<&str, Cost<'_>> {
+    alt((
+        map(preceded(tuple((tag("@@"), space0)), amount_unit), Cost::Total),
+        map(preceded(tuple((char('@'), space0)), amount_unit), Cost::PerUnit),
+    ))(input)
+}
+
+// Parses the end of a line or the input
+pub(crate) fn line_end(input: &str) -> IResult<&str, &str> {
+    alt((line_ending, eof))(input)
+}
+
+pub(crate) fn posting_indent(input: &str) -> IResult<&str, &str> {
     preceded(
         alt((tag("  "), tag("\t"))),
         space0
     )(input)
 }
 
-pub fn posting(input: &str) -> IResult<&str, Posting> {
+pub fn posting(input: &str) -> IResult<&str, Posting<'_>> {
     map(
         tuple((
                 posting_indent,
-                account,
+                posting_account,
                 space0,
                 opt(amount_unit),
                 space0,
+                opt(lot),
+                space0,
                 opt(assign_amount),
                 space0,
                 opt(cost),
                 space0,
                 opt(comment),
-                opt(char('\n'))
+                require(ParseError::InvalidAmount, line_end),
+                many0(note),
         )),
-        |(_, account, _, amount, _, assign, _, cost, _, comment, _)| Posting {
-            account: account,
-            amount: amount,
-            assign: assign,
-            cost: cost,
-            comment: comment,
+        |(_, (account, kind), _, amount, _, lot, _, assign, _, cost, _, comment, _, notes)| {
+            let tags = note_tags(comment, &notes);
+            let (date, edate) = posting_dates(comment, &notes, &tags);
+            Posting {
+                account,
+                kind,
+                date,
+                edate,
+                amount,
+                lot,
+                assign,
+                cost,
+                comment: comment.map(Cow::Borrowed),
+                notes: borrowed(notes),
+                tags,
+            }
         }
     )(input)
 }
 
-pub fn transaction(input: &str) -> IResult<&str, Transaction> {
+pub fn transaction(input: &str) -> IResult<&str, Transaction<'_>> {
     map(
         tuple((
             transaction_header,
-            many1(posting),
+            require(ParseError::MissingPosting, many1(posting)),
         )),
-        |(header, posting)| Transaction {
-            header: header,
-            posting: posting,
+        |(header, mut posting)| {
+            for p in posting.iter_mut() {
+                p.tags.inherit(&header.tags);
+            }
+            Transaction {
+                header,
+                posting,
+            }
         }
     )(input)
 }
 
 #[cfg(test)]
+#[allow(deprecated, clippy::zero_prefixed_literal)]
 mod test {
     use super::*;
 
+    fn ymd(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
     fn parse_assert_eq<'a, T, F>(mut f: F, s: &'a str, expected: (&str, T))
         where
             F: FnMut(&'a str) -> IResult<&'a str, T>,
@@ -322,8 +750,10 @@ mod test {
                     edate: None,
                     status: Status::Cleared,
                     code: None,
-                    description: "Withdraw",
+                    description: "Withdraw".into(),
                     comment: None,
+                    notes: vec![],
+                    tags: Tags::new(),
                 }
             ))
         );
@@ -336,8 +766,10 @@ mod test {
                     edate: None,
                     status: Status::Pending,
                     code: None,
-                    description: "Withdraw   ",
+                    description: "Withdraw   ".into(),
                     comment: None,
+                    notes: vec![],
+                    tags: Tags::new(),
                 }
             ))
         );
@@ -350,8 +782,10 @@ mod test {
                     edate: None,
                     status: Status::Uncleared,
                     code: None,
-                    description: "Withdraw ",
-                    comment: Some("comment"),
+                    description: "Withdraw ".into(),
+                    comment: Some("comment".into()),
+                    notes: vec![],
+                    tags: Tags::new(),
                 }
             ))
         );
@@ -368,8 +802,10 @@ mod test {
                     edate: Some(NaiveDate::from_ymd(2020, 12, 14)),
                     status: Status::Cleared,
                     code: None,
-                    description: "Withdraw",
+                    description: "Withdraw".into(),
                     comment: None,
+                    notes: vec![],
+                    tags: Tags::new(),
                 }
             ))
         );
@@ -385,9 +821,11 @@ mod test {
                     date: NaiveDate::from_ymd(2020, 11, 30),
                     edate: None,
                     status: Status::Cleared,
-                    code: Some("#100"),
-                    description: "Withdraw",
+                    code: Some("#100".into()),
+                    description: "Withdraw".into(),
                     comment: None,
+                    notes: vec![],
+                    tags: Tags::new(),
                 }
             ))
         );
@@ -403,9 +841,11 @@ mod test {
                     date: NaiveDate::from_ymd(2020, 11, 30),
                     edate: Some(NaiveDate::from_ymd(2020, 12, 11)),
                     status: Status::Cleared,
-                    code: Some("#100"),
-                    description: "Withdraw ",
-                    comment: Some("modified"),
+                    code: Some("#100".into()),
+                    description: "Withdraw ".into(),
+                    comment: Some("modified".into()),
+                    notes: vec![],
+                    tags: Tags::new(),
                 }
             ))
         );
@@ -460,10 +900,16 @@ mod test {
                 "",
                 Posting {
                     account: "Assets:Cash",
+                    kind: PostingKind::Real,
+                    date: None,
+                    edate: None,
                     amount: Some(Amount::from_str("100.05", "EUR").unwrap()),
+                    lot: None,
                     assign: None,
                     cost: None,
                     comment: None,
+                    notes: vec![],
+                    tags: Tags::new(),
                 }
             ))
         );
@@ -473,10 +919,16 @@ mod test {
                 "",
                 Posting {
                     account: "Assets:Cash",
+                    kind: PostingKind::Real,
+                    date: None,
+                    edate: None,
                     amount: Some(Amount::from_str("3000", "JPY").unwrap()),
+                    lot: None,
                     assign: None,
                     cost: None,
                     comment: None,
+                    notes: vec![],
+                    tags: Tags::new(),
                 }
             ))
         );
@@ -486,10 +938,16 @@ mod test {
                 "",
                 Posting {
                     account: "Liabilities:CreditCard",
+                    kind: PostingKind::Real,
+                    date: None,
+                    edate: None,
                     amount: Some(Amount::from_str("-3000", "JPY").unwrap()),
+                    lot: None,
                     assign: None,
                     cost: None,
-                    comment: Some("comment"),
+                    comment: Some("comment".into()),
+                    notes: vec![],
+                    tags: Tags::new(),
                 }
             ))
         );
@@ -503,10 +961,16 @@ mod test {
                 "",
                 Posting {
                     account: "Assets:Cash",
+                    kind: PostingKind::Real,
+                    date: None,
+                    edate: None,
                     amount: Some(Amount::from_str("500", "JPY").unwrap()),
+                    lot: None,
                     assign: Some(Amount::from_str("3000", "JPY").unwrap()),
                     cost: None,
                     comment: None,
+                    notes: vec![],
+                    tags: Tags::new(),
                 }
             ))
         );
@@ -516,10 +980,16 @@ mod test {
                 "",
                 Posting {
                     account: "Assets:Cash",
+                    kind: PostingKind::Real,
+                    date: None,
+                    edate: None,
                     amount: None,
+                    lot: None,
                     assign: Some(Amount::from_str("0", "").unwrap()),
                     cost: None,
-                    comment: Some("balance the cash"),
+                    comment: Some("balance the cash".into()),
+                    notes: vec![],
+                    tags: Tags::new(),
                 }
             ))
         );
@@ -533,13 +1003,73 @@ mod test {
                 "",
                 Posting {
                     account: "Assets:ETF",
+                    kind: PostingKind::Real,
+                    date: None,
+                    edate: None,
                     amount: Some(Amount::from_str("1", "VTI").unwrap()),
+                    lot: None,
                     assign: None,
-                    cost: Some(Amount::from_str("12300", "JPY").unwrap()),
+                    cost: Some(Cost::PerUnit(Amount::from_str("12300", "JPY").unwrap())),
                     comment: None,
+                    notes: vec![],
+                    tags: Tags::new(),
                 }
             ))
         );
+        assert_eq!(
+            posting("    Assets:ETF     -3 VTI @@ 100000 JPY\n").unwrap().1.cost,
+            Some(Cost::Total(Amount::from_str("100000", "JPY").unwrap()))
+        );
+    }
+
+    #[test]
+    fn convert_cost() {
+        let total = Cost::Total(Amount::from_str("100000", "JPY").unwrap());
+        let per_unit = Cost::PerUnit(Amount::from_str("12300", "JPY").unwrap());
+        let quantity = Decimal::from(-4);
+
+        assert_eq!(total.per_unit(quantity), Amount::new(Decimal::from(25000), "JPY"));
+        assert_eq!(total.total(quantity), Amount::new(Decimal::from(-100000), "JPY"));
+        assert_eq!(per_unit.total(quantity), Amount::new(Decimal::from(-49200), "JPY"));
+        assert_eq!(total.to_string(), "@@ 100000 JPY");
+    }
+
+    #[test]
+    fn parse_posting_with_lot() {
+        let s = "    Assets:ETF     -10 VTI {12300 JPY} [2021-03-01] (NISA) @ 23000 JPY\n";
+        let (_, p) = posting(s).unwrap();
+
+        assert_eq!(
+            p.lot,
+            Some(Lot {
+                cost: Amount::from_str("12300", "JPY").ok(),
+                date: Some(ymd(2021, 3, 1)),
+                note: Some("NISA".into()),
+            })
+        );
+        assert_eq!(p.cost, Amount::from_str("23000", "JPY").ok().map(Cost::PerUnit));
+        assert_eq!(
+            format!("{:30}", p),
+            "    Assets:ETF         -10 VTI {12300 JPY} [2021-03-01] (NISA) @ 23000 JPY"
+        );
+        assert_eq!(
+            posting("    Assets:ETF     1 VTI [2021-03-01]\n").unwrap().1.lot,
+            Some(Lot { cost: None, date: Some(ymd(2021, 3, 1)), note: None })
+        );
+        assert!(posting("    Assets:ETF     1 VTI {12300 JPY\n").is_err());
+    }
+
+    #[test]
+    fn parse_virtual_posting() {
+        let (_, p) = posting("    (Budget:Food)  -500 JPY\n").unwrap();
+        assert_eq!((p.account, p.kind), ("Budget:Food", PostingKind::Virtual));
+        assert_eq!(format!("{:30}", p), "    (Budget:Food)     -500 JPY");
+
+        let (_, p) = posting("    [Budget:Food]\n").unwrap();
+        assert_eq!((p.account, p.kind), ("Budget:Food", PostingKind::BalancedVirtual));
+        assert_eq!(p.to_string(), "    [Budget:Food]");
+
+        assert!(posting("    (Budget:Food  -500 JPY\n").is_err());
     }
 
     #[test]
@@ -550,10 +1080,16 @@ mod test {
                 "",
                 Posting {
                     account: "Assets:Cash",
+                    kind: PostingKind::Real,
+                    date: None,
+                    edate: None,
                     amount: None,
+                    lot: None,
                     assign: None,
                     cost: None,
                     comment: None,
+                    notes: vec![],
+                    tags: Tags::new(),
                 }
             ))
         );
@@ -577,23 +1113,37 @@ mod test {
                     edate: None,
                     status: Status::Cleared,
                     code: None,
-                    description: "引き出し",
+                    description: "引き出し".into(),
                     comment: None,
+                    notes: vec![],
+                    tags: Tags::new(),
                 },
                 posting: vec![
                     Posting {
                         account: "資産:現金",
+                        kind: PostingKind::Real,
+                        date: None,
+                        edate: None,
                         amount: Amount::from_str("1000", "JPY").ok(),
+                        lot: None,
                         assign: None,
                         cost: None,
                         comment: None,
+                        notes: vec![],
+                        tags: Tags::new(),
                     },
                     Posting {
                         account: "資産:普通預金:JP",
+                        kind: PostingKind::Real,
+                        date: None,
+                        edate: None,
                         amount: Amount::from_str("-1000", "JPY").ok(),
+                        lot: None,
                         assign: None,
                         cost: None,
                         comment: None,
+                        notes: vec![],
+                        tags: Tags::new(),
                     },
                 ],
             }))
@@ -615,33 +1165,163 @@ mod test {
                     edate: None,
                     status: Status::Cleared,
                     code: None,
-                    description: "Tomod's",
+                    description: "Tomod's".into(),
                     comment: None,
+                    notes: vec![],
+                    tags: Tags::new(),
                 },
                 posting: vec![
                     Posting {
                         account: "費用:食費",
+                        kind: PostingKind::Real,
+                        date: None,
+                        edate: None,
                         amount: Amount::from_str("500", "JPY").ok(),
+                        lot: None,
                         assign: None,
                         cost: None,
                         comment: None,
+                        notes: vec![],
+                        tags: Tags::new(),
                     },
                     Posting {
                         account: "費用:消耗品費",
+                        kind: PostingKind::Real,
+                        date: None,
+                        edate: None,
                         amount: Amount::from_str("1000", "JPY").ok(),
+                        lot: None,
                         assign: None,
                         cost: None,
                         comment: None,
+                        notes: vec![],
+                        tags: Tags::new(),
                     },
                     Posting {
                         account: "資産:現金",
+                        kind: PostingKind::Real,
+                        date: None,
+                        edate: None,
                         amount: None,
+                        lot: None,
                         assign: None,
                         cost: None,
                         comment: None,
+                        notes: vec![],
+                        tags: Tags::new(),
                     },
                 ],
             }))
         );
     }
+
+    #[test]
+    fn inherit_transaction_tags() {
+        let s = r#"2021-09-20 * Tomod's ; :receipt:
+    費用:食費           500 JPY ; project: 引っ越し
+    資産:現金
+"#;
+        let (_, tx) = transaction(s).unwrap();
+
+        assert!(tx.header.tags.contains("receipt"));
+        assert!(tx.posting[0].tags.contains("receipt"));
+        assert!(tx.posting[0].tags.contains("project"));
+        assert!(tx.posting[1].tags.contains("receipt"));
+        assert!(!tx.posting[1].tags.contains("project"));
+    }
+
+    #[test]
+    fn parse_posting_dates() {
+        let dates = |s| {
+            let (_, p) = posting(s).unwrap();
+            (p.date, p.edate)
+        };
+
+        assert_eq!(
+            dates("    負債:カード  -500 JPY ; [2021-09-20=2021-10-27]\n"),
+            (Some(ymd(2021, 9, 20)), Some(ymd(2021, 10, 27)))
+        );
+        assert_eq!(dates("    負債:カード  -500 JPY ; 引き落とし [=2021-10-27]\n"), (None, Some(ymd(2021, 10, 27))));
+        assert_eq!(
+            dates("    負債:カード  -500 JPY\n    ; date: 2021-09-21\n    ; date2: 2021-10-27\n"),
+            (Some(ymd(2021, 9, 21)), Some(ymd(2021, 10, 27)))
+        );
+        assert_eq!(dates("    負債:カード  -500 JPY ; [メモ]\n"), (None, None));
+    }
+
+    #[test]
+    fn parse_note_lines() {
+        let s = r#"2021-09-20 * Tomod's
+    ; レシートあり
+    ; project: 引っ越し
+    費用:食費           500 JPY ; 弁当
+    ; :receipt:
+    ;   2 個
+    資産:現金
+"#;
+        let (rest, tx) = transaction(s).unwrap();
+
+        assert_eq!(rest, "");
+        assert_eq!(tx.header.notes, vec![" レシートあり", " project: 引っ越し"]);
+        assert_eq!(tx.posting[0].notes, vec![" :receipt:", "   2 個"]);
+        assert!(tx.posting[0].tags.contains("receipt"));
+        assert!(tx.posting[1].tags.contains("project"));
+        assert!(tx.posting[1].notes.is_empty());
+        assert_eq!(
+            tx.to_string(),
+            r#"2021-09-20 * Tomod's
+    ; レシートあり
+    ; project: 引っ越し
+    費用:食費                                500 JPY  ; 弁当
+    ; :receipt:
+    ;   2 個
+    資産:現金
+"#
+        );
+    }
+
+    #[test]
+    fn print_transaction() {
+        let s = r#"2021-09-20=2021-10-27 ! (#12) Tomod's ; receipt
+    費用:食費  500 JPY
+    費用:消耗品費       1000 JPY ; 洗剤
+  資産:ETF   1 VTI @ 12300 JPY
+    資産:現金  = 700 JPY
+    資産:普通預金:JP
+"#;
+        let (_, tx) = transaction(s).unwrap();
+
+        assert_eq!(
+            tx.to_string(),
+            r#"2021-09-20=2021-10-27 ! (#12) Tomod's ; receipt
+    費用:食費                                500 JPY
+    費用:消耗品費                           1000 JPY  ; 洗剤
+    資産:ETF                                   1 VTI @ 12300 JPY
+    資産:現金  = 700 JPY
+    資産:普通預金:JP
+"#
+        );
+        assert_eq!(
+            format!("{:30}", tx.posting[0]),
+            "    費用:食費          500 JPY"
+        );
+    }
+
+    #[test]
+    fn print_and_reparse_transaction() {
+        vec![
+            "2021-09-16 * 引き出し\n    資産:現金  1000 JPY\n    資産:普通預金:JP\n",
+            "2020-11-30 Withdraw ; comment\n    Assets:Cash    =0 ; balance the cash\n",
+            "2020-11-30=2020-12-11 * (#100) Withdraw   \n    Assets:Cash 100.50 EUR = 3000.00 EUR\n    Equity\n",
+            "2021-03-01 * Buy\n    Assets:ETF  3 VTI @@ 100000 JPY\n    Assets:Broker\n",
+            "2021-09-20 * Tomod's\n    Expenses:Food  500 JPY\n    Assets:Cash\n    (Budget:Food)  -500 JPY\n    [Budget:Rest]\n",
+            "2021-09-20 * Tomod's\n    ; :receipt:\n    Expenses:Food  500 JPY\n    ; Store: Tomod's\n    Assets:Cash\n",
+        ]
+            .into_iter()
+            .for_each(|s| {
+                let (_, tx) = transaction(s).unwrap();
+                let printed = tx.to_string();
+                assert_eq!(transaction(&printed), Ok(("", tx)));
+            });
+    }
 }
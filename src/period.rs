@@ -0,0 +1,418 @@
+use chrono::{Datelike, Days, Months, NaiveDate};
+
+/// Range of dates from `begin` up to but not including `end`.
+///
+/// An open side is unbounded.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct DateSpan {
+    pub begin: Option<NaiveDate>,
+    pub end: Option<NaiveDate>,
+}
+
+impl DateSpan {
+    pub fn new(begin: Option<NaiveDate>, end: Option<NaiveDate>) -> Self {
+        Self {
+            begin,
+            end,
+        }
+    }
+
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.begin.is_none_or(|b| b <= date) && self.end.is_none_or(|e| date < e)
+    }
+
+    /// Parses a span like `2021`, `2021-09`, `2021-09-16` or a range of them
+    /// like `2021-01..2021-07`, whose end is exclusive. Either side of a
+    /// range may be omitted.
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.split_once("..") {
+            Some((begin, end)) => {
+                let begin = if begin.is_empty() { None } else { Some(partial_date(begin)?.begin?) };
+                let end = if end.is_empty() { None } else { Some(partial_date(end)?.begin?) };
+                Some(Self::new(begin, end))
+            },
+            None => partial_date(s),
+        }
+    }
+}
+
+/// Length of the intervals into which a report is divided.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interval {
+    Daily,
+    /// Weeks starting on Monday
+    Weekly,
+    Monthly,
+    Quarterly,
+    Yearly,
+    /// Fiscal years starting on the first day of a month, which are named
+    /// after the calendar year they start in
+    FiscalYearly(u32),
+}
+
+impl Interval {
+    // Parses a unit like `month` in `every month` or `last month` at the
+    // beginning of `words`, and returns the rest.
+    fn from_unit<'w, 's>(words: &'w [&'s str], fiscal_year_start: u32) -> Option<(Self, &'w [&'s str])> {
+        match words {
+            ["fiscal", "year", rest @ ..] => Some((Interval::FiscalYearly(fiscal_year_start), rest)),
+            ["day", rest @ ..] => Some((Interval::Daily, rest)),
+            ["week", rest @ ..] => Some((Interval::Weekly, rest)),
+            ["month", rest @ ..] => Some((Interval::Monthly, rest)),
+            ["quarter", rest @ ..] => Some((Interval::Quarterly, rest)),
+            ["year", rest @ ..] => Some((Interval::Yearly, rest)),
+            _ => None,
+        }
+    }
+
+    // Parses an interval like `monthly`.
+    fn from_adverb(s: &str) -> Option<Self> {
+        match s {
+            "daily" => Some(Interval::Daily),
+            "weekly" => Some(Interval::Weekly),
+            "monthly" => Some(Interval::Monthly),
+            "quarterly" => Some(Interval::Quarterly),
+            "yearly" => Some(Interval::Yearly),
+            _ => None,
+        }
+    }
+
+    /// Returns the first day of the interval containing `date`.
+    pub fn start(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Interval::Daily => date,
+            Interval::Weekly => date - Days::new(date.weekday().num_days_from_monday() as u64),
+            Interval::Monthly => date.with_day(1).unwrap(),
+            Interval::Quarterly => NaiveDate::from_ymd_opt(date.year(), (date.month() - 1) / 3 * 3 + 1, 1).unwrap(),
+            Interval::Yearly => NaiveDate::from_ymd_opt(date.year(), 1, 1).unwrap(),
+            Interval::FiscalYearly(month) => {
+                let year = if date.month() >= *month { date.year() } else { date.year() - 1 };
+                NaiveDate::from_ymd_opt(year, *month, 1).unwrap()
+            },
+        }
+    }
+
+    /// Returns the first day of the interval after the one containing
+    /// `date`.
+    pub fn next(&self, date: NaiveDate) -> NaiveDate {
+        let start = self.start(date);
+        match self {
+            Interval::Daily => start + Days::new(1),
+            Interval::Weekly => start + Days::new(7),
+            Interval::Monthly => add_months(start, 1),
+            Interval::Quarterly => add_months(start, 3),
+            Interval::Yearly | Interval::FiscalYearly(_) => add_months(start, 12),
+        }
+    }
+
+    /// Returns the first day of the interval before the one containing
+    /// `date`.
+    pub fn previous(&self, date: NaiveDate) -> NaiveDate {
+        self.start(self.start(date) - Days::new(1))
+    }
+
+    /// Returns the date `n` intervals after `date`, which is the same day of
+    /// the month if possible or the last day of the month otherwise.
+    pub fn add(&self, date: NaiveDate, n: u32) -> Option<NaiveDate> {
+        match self {
+            Interval::Daily => date.checked_add_days(Days::new(n as u64)),
+            Interval::Weekly => date.checked_add_days(Days::new(n as u64 * 7)),
+            Interval::Monthly => date.checked_add_months(Months::new(n)),
+            Interval::Quarterly => date.checked_add_months(Months::new(n * 3)),
+            Interval::Yearly | Interval::FiscalYearly(_) => date.checked_add_months(Months::new(n * 12)),
+        }
+    }
+
+    /// Returns the interval containing `date`.
+    pub fn span(&self, date: NaiveDate) -> DateSpan {
+        DateSpan::new(Some(self.start(date)), Some(self.next(date)))
+    }
+
+    /// Returns the name of the interval containing `date`, like `2021-09`
+    /// or `2021Q3`. A week is named after its first day and a fiscal year
+    /// like `FY2021`.
+    pub fn label(&self, date: NaiveDate) -> String {
+        let start = self.start(date);
+        match self {
+            Interval::Daily | Interval::Weekly => start.format("%Y-%m-%d").to_string(),
+            Interval::Monthly => start.format("%Y-%m").to_string(),
+            Interval::Quarterly => format!("{}Q{}", start.year(), (start.month() - 1) / 3 + 1),
+            Interval::Yearly => start.year().to_string(),
+            Interval::FiscalYearly(_) => format!("FY{}", start.year()),
+        }
+    }
+
+    /// Divides the dates from `begin` up to but not including `end` into
+    /// intervals. The first and the last intervals are whole even if
+    /// `begin` and `end` fall in the middle of them.
+    pub fn split(&self, begin: NaiveDate, end: NaiveDate) -> Vec<DateSpan> {
+        let mut spans = Vec::new();
+        let mut date = self.start(begin);
+
+        while date < end {
+            spans.push(self.span(date));
+            date = self.next(date);
+        }
+
+        spans
+    }
+}
+
+/// Span of dates optionally divided into intervals.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Period {
+    pub span: DateSpan,
+    pub interval: Option<Interval>,
+}
+
+impl Period {
+    /// Parses a period expression like `2021Q3`, `last month`,
+    /// `from 2021-04-01 to 2022-03-31`, `every month` or
+    /// `monthly in 2021`.
+    ///
+    /// An interval like `every month`, `weekly` comes first, followed by a
+    /// span which is a single date, a relative date like `this year` or a
+    /// range written with `from` and `to`. The end of a range is exclusive
+    /// and relative dates are resolved against `today`.
+    ///
+    /// Fiscal years start in January.
+    pub fn parse(s: &str, today: NaiveDate) -> Option<Self> {
+        Self::parse_with(s, today, 1)
+    }
+
+    /// Returns true if `s` is a period expression which `parse` accepts on
+    /// any date.
+    pub fn is_valid(s: &str) -> bool {
+        // Relative dates resolve against any date away from the limits of
+        // `NaiveDate`, so that only the syntax is checked
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        Self::parse(s, date).is_some()
+    }
+
+    /// Parses a period expression like `Period::parse` with fiscal years
+    /// starting in `fiscal_year_start`, the number of a month.
+    ///
+    /// Fiscal years are written like `FY2021`, `this fiscal year` or
+    /// `every fiscal year`.
+    pub fn parse_with(s: &str, today: NaiveDate, fiscal_year_start: u32) -> Option<Self> {
+        if !(1..=12).contains(&fiscal_year_start) {
+            return None;
+        }
+        let lower = s.to_lowercase();
+        let words: Vec<_> = lower.split_whitespace().collect();
+
+        let (interval, rest) = match words.split_first() {
+            Some((&"every", rest)) => {
+                let (interval, rest) = Interval::from_unit(rest, fiscal_year_start)?;
+                (Some(interval), rest)
+            },
+            Some((word, rest)) => match Interval::from_adverb(word) {
+                Some(interval) => (Some(interval), rest),
+                None => (None, &words[..]),
+            },
+            None => (None, &words[..]),
+        };
+
+        Some(Self {
+            span: span(rest, today, fiscal_year_start)?,
+            interval,
+        })
+    }
+}
+
+// Parses the span of a period expression.
+fn span(words: &[&str], today: NaiveDate, fiscal_year_start: u32) -> Option<DateSpan> {
+    let words = words.strip_prefix(&["in"]).unwrap_or(words);
+    let (from, to) = match words.iter().position(|w| *w == "to") {
+        Some(i) => (&words[..i], Some(&words[i + 1..])),
+        None => (words, None),
+    };
+
+    if from.first() != Some(&"from") && to.is_none() {
+        return if words.is_empty() { Some(DateSpan::default()) } else { date_term(words, today, fiscal_year_start) };
+    }
+
+    let from = from.strip_prefix(&["from"]).unwrap_or(from);
+    let begin = if from.is_empty() { None } else { Some(date_term(from, today, fiscal_year_start)?.begin?) };
+    let end = match to {
+        Some(to) => Some(date_term(to, today, fiscal_year_start)?.begin?),
+        None => None,
+    };
+    if begin.is_none() && end.is_none() {
+        return None;
+    }
+
+    Some(DateSpan::new(begin, end))
+}
+
+// Parses a date like `2021Q3` or `last month` as the span covering it.
+fn date_term(words: &[&str], today: NaiveDate, fiscal_year_start: u32) -> Option<DateSpan> {
+    match words {
+        ["today"] => Some(Interval::Daily.span(today)),
+        ["yesterday"] => Some(Interval::Daily.span(today.pred_opt()?)),
+        ["tomorrow"] => Some(Interval::Daily.span(today.succ_opt()?)),
+        [relative, unit @ ..] if !unit.is_empty() => {
+            let interval = match Interval::from_unit(unit, fiscal_year_start)? {
+                (interval, []) => interval,
+                _ => return None,
+            };
+            let date = match *relative {
+                "this" => today,
+                "last" => interval.previous(today),
+                "next" => interval.next(today),
+                _ => return None,
+            };
+            Some(interval.span(date))
+        },
+        [word] => quarter(word)
+            .or_else(|| fiscal_year(word, fiscal_year_start))
+            .or_else(|| DateSpan::parse(word)),
+        _ => None,
+    }
+}
+
+// Parses a quarter like `2021q3`.
+fn quarter(s: &str) -> Option<DateSpan> {
+    let (year, q) = s.split_once('q')?;
+    let q: u32 = q.parse().ok()?;
+    if !(1..=4).contains(&q) {
+        return None;
+    }
+
+    let begin = NaiveDate::from_ymd_opt(year.parse().ok()?, q * 3 - 2, 1)?;
+    Some(Interval::Quarterly.span(begin))
+}
+
+// Parses a fiscal year like `fy2021`.
+fn fiscal_year(s: &str, start: u32) -> Option<DateSpan> {
+    let year = s.strip_prefix("fy")?.parse().ok()?;
+    let begin = NaiveDate::from_ymd_opt(year, start, 1)?;
+    Some(Interval::FiscalYearly(start).span(begin))
+}
+
+// Returns the first day of the month `n` months after the month of `date`.
+fn add_months(date: NaiveDate, n: u32) -> NaiveDate {
+    let months = date.year() * 12 + date.month0() as i32 + n as i32;
+    NaiveDate::from_ymd_opt(months.div_euclid(12), months.rem_euclid(12) as u32 + 1, 1).unwrap()
+}
+
+// Parses a year, a month or a day as the span covering it.
+fn partial_date(s: &str) -> Option<DateSpan> {
+    let parts: Vec<_> = s.split(['-', '/']).collect();
+    let numbers: Vec<u32> = parts.iter()
+        .map(|p| p.parse().ok())
+        .collect::<Option<_>>()?;
+
+    let (begin, end) = match numbers[..] {
+        [y] => {
+            let y = y as i32;
+            (NaiveDate::from_ymd_opt(y, 1, 1)?, NaiveDate::from_ymd_opt(y + 1, 1, 1)?)
+        },
+        [y, m] => {
+            let begin = NaiveDate::from_ymd_opt(y as i32, m, 1)?;
+            (begin, add_months(begin, 1))
+        },
+        [y, m, d] => {
+            let begin = NaiveDate::from_ymd_opt(y as i32, m, d)?;
+            (begin, begin.succ_opt()?)
+        },
+        _ => return None,
+    };
+
+    Some(DateSpan::new(Some(begin), Some(end)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ymd(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn parse_date_span() {
+        assert_eq!(DateSpan::parse("2021"), Some(DateSpan::new(Some(ymd(2021, 1, 1)), Some(ymd(2022, 1, 1)))));
+        assert_eq!(DateSpan::parse("2021/12"), Some(DateSpan::new(Some(ymd(2021, 12, 1)), Some(ymd(2022, 1, 1)))));
+        assert_eq!(DateSpan::parse("2021-09-16"), Some(DateSpan::new(Some(ymd(2021, 9, 16)), Some(ymd(2021, 9, 17)))));
+        assert_eq!(DateSpan::parse("2021-01..2021-07"), Some(DateSpan::new(Some(ymd(2021, 1, 1)), Some(ymd(2021, 7, 1)))));
+        assert_eq!(DateSpan::parse("..2021-07"), Some(DateSpan::new(None, Some(ymd(2021, 7, 1)))));
+        assert_eq!(DateSpan::parse("2021-13"), None);
+        assert_eq!(DateSpan::parse("Sep"), None);
+    }
+
+    #[test]
+    fn contain_dates() {
+        let span = DateSpan::parse("2021-09").unwrap();
+
+        assert!(span.contains(ymd(2021, 9, 1)));
+        assert!(span.contains(ymd(2021, 9, 30)));
+        assert!(!span.contains(ymd(2021, 10, 1)));
+        assert!(DateSpan::default().contains(ymd(2021, 10, 1)));
+    }
+
+    #[test]
+    fn parse_periods() {
+        let today = ymd(2021, 9, 16);
+        let parse = |s| Period::parse(s, today);
+        let period = |begin, end, interval| Some(Period {
+            span: DateSpan::new(begin, end),
+            interval,
+        });
+
+        assert_eq!(parse("2021Q3"), period(Some(ymd(2021, 7, 1)), Some(ymd(2021, 10, 1)), None));
+        assert_eq!(parse("last month"), period(Some(ymd(2021, 8, 1)), Some(ymd(2021, 9, 1)), None));
+        assert_eq!(parse("this week"), period(Some(ymd(2021, 9, 13)), Some(ymd(2021, 9, 20)), None));
+        assert_eq!(
+            parse("from 2021-04-01 to 2022-03-31"),
+            period(Some(ymd(2021, 4, 1)), Some(ymd(2022, 3, 31)), None)
+        );
+        assert_eq!(parse("to next year"), period(None, Some(ymd(2022, 1, 1)), None));
+        assert_eq!(parse("every month"), period(None, None, Some(Interval::Monthly)));
+        assert_eq!(
+            parse("Weekly from 2021-09"),
+            period(Some(ymd(2021, 9, 1)), None, Some(Interval::Weekly))
+        );
+        assert_eq!(
+            parse("quarterly in 2021"),
+            period(Some(ymd(2021, 1, 1)), Some(ymd(2022, 1, 1)), Some(Interval::Quarterly))
+        );
+        assert_eq!(parse("2021Q5"), None);
+        assert_eq!(parse("every fortnight"), None);
+        assert_eq!(parse("from"), None);
+
+        assert!(Period::is_valid("monthly from next month"));
+        assert!(!Period::is_valid("every fortnight"));
+    }
+
+    #[test]
+    fn parse_fiscal_years() {
+        let today = ymd(2021, 3, 16);
+        let parse = |s| Period::parse_with(s, today, 4);
+        let fy2021 = DateSpan::new(Some(ymd(2021, 4, 1)), Some(ymd(2022, 4, 1)));
+
+        assert_eq!(parse("FY2021").map(|p| p.span), Some(fy2021));
+        assert_eq!(parse("next fiscal year").map(|p| p.span), Some(fy2021));
+        assert_eq!(
+            parse("this fiscal year").map(|p| p.span),
+            Some(DateSpan::new(Some(ymd(2020, 4, 1)), Some(ymd(2021, 4, 1))))
+        );
+        assert_eq!(parse("this year").map(|p| p.span), DateSpan::parse("2021"));
+        assert_eq!(parse("every fiscal year in 2021").map(|p| p.interval), Some(Some(Interval::FiscalYearly(4))));
+        assert_eq!(Period::parse_with("FY2021", today, 13), None);
+        assert_eq!(Interval::FiscalYearly(4).label(ymd(2022, 3, 31)), "FY2021");
+    }
+
+    #[test]
+    fn split_into_intervals() {
+        let labels = |interval: Interval, begin, end| interval.split(begin, end).iter()
+            .map(|span| interval.label(span.begin.unwrap()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(labels(Interval::Monthly, ymd(2021, 11, 15), ymd(2022, 2, 1)), vec!["2021-11", "2021-12", "2022-01"]);
+        assert_eq!(labels(Interval::Quarterly, ymd(2021, 9, 16), ymd(2021, 10, 2)), vec!["2021Q3", "2021Q4"]);
+        assert_eq!(labels(Interval::FiscalYearly(4), ymd(2021, 1, 1), ymd(2021, 4, 2)), vec!["FY2020", "FY2021"]);
+        assert_eq!(labels(Interval::Weekly, ymd(2021, 9, 16), ymd(2021, 9, 21)), vec!["2021-09-13", "2021-09-20"]);
+        assert_eq!(labels(Interval::Daily, ymd(2021, 9, 16), ymd(2021, 9, 16)), Vec::<String>::new());
+    }
+}
@@ -0,0 +1,149 @@
+use crate::parser::transaction::Amount;
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+/// Dated prices of commodities.
+///
+/// Prices come from `P` directives and from the costs of postings. A
+/// conversion uses the latest price on or before the given date, and may go
+/// through the inverse of a price or through other commodities.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PriceDb<'a> {
+    // Prices of one unit of the first commodity in the second one
+    prices: HashMap<(&'a str, &'a str), BTreeMap<NaiveDate, Decimal>>,
+    // Commodities which have a price in terms of each other
+    neighbors: HashMap<&'a str, HashSet<&'a str>>,
+}
+
+impl<'a> PriceDb<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that one `unit` costs `price` on `date`.
+    ///
+    /// A later price on the same date overrides the earlier one.
+    pub fn add(&mut self, date: NaiveDate, unit: &'a str, price: &Amount<'a>) {
+        if unit == price.unit || price.price.is_zero() {
+            return;
+        }
+
+        self.prices.entry((unit, price.unit))
+            .or_default()
+            .insert(date, price.price);
+        self.neighbors.entry(unit).or_default().insert(price.unit);
+        self.neighbors.entry(price.unit).or_default().insert(unit);
+    }
+
+    /// Returns the commodity named `unit` if it has any price.
+    pub fn commodity(&self, unit: &str) -> Option<&'a str> {
+        self.neighbors.get_key_value(unit).map(|(k, _)| *k)
+    }
+
+    // Returns the latest rate from `from` to `to` on or before `date`
+    // together with its date, looking at the prices in both directions.
+    fn direct_rate(&self, from: &str, to: &str, date: NaiveDate) -> Option<(NaiveDate, Decimal)> {
+        let latest = |key: (&str, &str)| {
+            self.prices.get(&key)
+                .and_then(|p| p.range(..=date).next_back())
+                .map(|(d, r)| (*d, *r))
+        };
+        let forward = latest((from, to));
+        let inverse = latest((to, from)).map(|(d, r)| (d, Decimal::ONE / r));
+
+        match (forward, inverse) {
+            (Some(f), Some(i)) => Some(if i.0 > f.0 { i } else { f }),
+            (f, i) => f.or(i),
+        }
+    }
+
+    /// Returns the value of one `from` in `to` on `date`.
+    ///
+    /// The conversion through the fewest commodities is chosen if there is
+    /// no price between them.
+    pub fn rate(&self, from: &str, to: &str, date: NaiveDate) -> Option<Decimal> {
+        if from == to {
+            return Some(Decimal::ONE);
+        }
+
+        let mut visited = HashSet::new();
+        let mut queue = VecDeque::new();
+        visited.insert(from);
+        queue.push_back((from, Decimal::ONE));
+
+        while let Some((unit, rate)) = queue.pop_front() {
+            for next in self.neighbors.get(unit).into_iter().flatten() {
+                if visited.contains(next) {
+                    continue;
+                }
+                if let Some((_, r)) = self.direct_rate(unit, next, date) {
+                    if *next == to {
+                        return Some(rate * r);
+                    }
+                    visited.insert(next);
+                    queue.push_back((next, rate * r));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Converts `amount` to the commodity `to` at the prices on `date`.
+    pub fn convert(&self, amount: &Amount<'a>, to: &'a str, date: NaiveDate) -> Option<Amount<'a>> {
+        self.rate(amount.unit, to, date)
+            .map(|rate| Amount::new(amount.price * rate, to))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ymd(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn amount(price: &'static str, unit: &'static str) -> Amount<'static> {
+        Amount::from_str(price, unit).unwrap()
+    }
+
+    fn db() -> PriceDb<'static> {
+        let mut db = PriceDb::new();
+        db.add(ymd(2021, 9, 1), "VTI", &amount("220", "USD"));
+        db.add(ymd(2021, 9, 16), "VTI", &amount("230", "USD"));
+        db.add(ymd(2021, 9, 10), "USD", &amount("110", "JPY"));
+        db.add(ymd(2021, 9, 20), "JPY", &amount("0.008", "USD"));
+        db
+    }
+
+    #[test]
+    fn lookup_nearest_earlier_price() {
+        let db = db();
+
+        assert_eq!(db.rate("VTI", "USD", ymd(2021, 8, 31)), None);
+        assert_eq!(db.rate("VTI", "USD", ymd(2021, 9, 15)), Some(Decimal::from(220)));
+        assert_eq!(db.rate("VTI", "USD", ymd(2021, 9, 16)), Some(Decimal::from(230)));
+        assert_eq!(db.rate("VTI", "USD", ymd(2022, 1, 1)), Some(Decimal::from(230)));
+    }
+
+    #[test]
+    fn convert_by_inverse_price() {
+        let db = db();
+
+        assert_eq!(db.rate("JPY", "USD", ymd(2021, 9, 15)), Some(Decimal::ONE / Decimal::from(110)));
+        assert_eq!(db.rate("USD", "JPY", ymd(2021, 9, 20)), Some(Decimal::from(125)));
+    }
+
+    #[test]
+    fn convert_transitively() {
+        let db = db();
+
+        assert_eq!(
+            db.convert(&amount("2", "VTI"), "JPY", ymd(2021, 9, 16)),
+            Some(amount("50600", "JPY"))
+        );
+        assert_eq!(db.convert(&amount("2", "VTI"), "EUR", ymd(2021, 9, 16)), None);
+    }
+}
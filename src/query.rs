@@ -0,0 +1,382 @@
+use crate::intern::{AccountTable, CommodityTable, PostingIds};
+use crate::parser::transaction::{Posting, Status, Transaction};
+use crate::period::DateSpan;
+use chrono::NaiveDate;
+use regex::{Regex, RegexBuilder};
+use rust_decimal::Decimal;
+use std::cmp::Ordering;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum QueryError {
+    #[error("Invalid regular expression in {term}: {error}")]
+    Regex {
+        term: String,
+        error: regex::Error,
+    },
+    #[error("Invalid amount condition {0}")]
+    Amount(String),
+    #[error("Invalid date {0}")]
+    Date(String),
+    #[error("Invalid status {0}")]
+    Status(String),
+    #[error("Unclosed quote in {0}")]
+    UnclosedQuote(String),
+}
+
+/// Comparison of amounts given by `amt:`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AmountCondition {
+    /// Orderings of the amount against `value` which match
+    pub orderings: &'static [Ordering],
+    pub value: Decimal,
+    /// Compare the amount with its sign. Otherwise the absolute value is
+    /// compared.
+    pub signed: bool,
+}
+
+impl AmountCondition {
+    fn parse(s: &str) -> Option<Self> {
+        let (orderings, rest): (&'static [Ordering], _) = if let Some(rest) = s.strip_prefix("<=") {
+            (&[Ordering::Less, Ordering::Equal], rest)
+        } else if let Some(rest) = s.strip_prefix(">=") {
+            (&[Ordering::Greater, Ordering::Equal], rest)
+        } else if let Some(rest) = s.strip_prefix('<') {
+            (&[Ordering::Less], rest)
+        } else if let Some(rest) = s.strip_prefix('>') {
+            (&[Ordering::Greater], rest)
+        } else {
+            (&[Ordering::Equal], s.strip_prefix('=').unwrap_or(s))
+        };
+
+        Some(Self {
+            orderings,
+            value: rest.parse().ok()?,
+            signed: rest.starts_with(['+', '-']),
+        })
+    }
+
+    pub fn matches(&self, price: Decimal) -> bool {
+        let price = if self.signed { price } else { price.abs() };
+        self.orderings.contains(&price.cmp(&self.value))
+    }
+}
+
+/// Predicate on postings.
+///
+/// A query is written as terms like `acct:資産 desc:Amazon amt:>5000
+/// date:2021-09 status:* tag:project=x cur:JPY`. A term without a prefix
+/// is the same as `acct:`, as is a term like `/^費用:/` written in the
+/// syntax of automated transactions, and `not:` negates the term
+/// following it. The terms of the same kind are combined with or, and the
+/// terms of different kinds and negated terms are combined with and.
+#[derive(Debug, Default, Clone)]
+pub enum Query {
+    /// Matches every posting
+    #[default]
+    Any,
+    /// Account name matching a regular expression
+    Account(Regex),
+    /// Transaction description matching a regular expression
+    Description(Regex),
+    Amount(AmountCondition),
+    Date(DateSpan),
+    Status(Status),
+    /// Tag whose name and optionally value match regular expressions
+    Tag(Regex, Option<Regex>),
+    /// Commodity of the amount matching a regular expression exactly
+    Commodity(Regex),
+    Not(Box<Query>),
+    And(Vec<Query>),
+    Or(Vec<Query>),
+}
+
+// Kinds of terms, in the order of the conjunction
+const KINDS: &[&str] = &["acct", "desc", "amt", "date", "status", "tag", "cur"];
+
+fn regex(pattern: &str, term: &str) -> Result<Regex, QueryError> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .build()
+        .map_err(|error| QueryError::Regex { term: term.to_owned(), error })
+}
+
+impl Query {
+    /// Parses a query written in a line, where terms are separated by
+    /// spaces. A term can be quoted like `desc:'Tomod s'`.
+    pub fn parse(s: &str) -> Result<Self, QueryError> {
+        Self::from_terms(&split_terms(s)?)
+    }
+
+    /// Parses a query given as separate terms, like command line arguments.
+    pub fn from_terms<S: AsRef<str>>(terms: &[S]) -> Result<Self, QueryError> {
+        let mut groups: Vec<Vec<Query>> = vec![Vec::new(); KINDS.len()];
+        let mut negated = Vec::new();
+
+        for term in terms.iter().map(|t| t.as_ref()) {
+            match term.strip_prefix("not:") {
+                Some(rest) => negated.push(Query::Not(Box::new(Self::term(rest)?.1))),
+                None => {
+                    let (kind, query) = Self::term(term)?;
+                    groups[kind].push(query);
+                },
+            }
+        }
+
+        let mut conjunction: Vec<_> = groups.into_iter()
+            .filter(|g| !g.is_empty())
+            .map(|mut g| if g.len() == 1 { g.pop().unwrap() } else { Query::Or(g) })
+            .collect();
+        conjunction.extend(negated);
+
+        Ok(match conjunction.len() {
+            0 => Query::Any,
+            1 => conjunction.pop().unwrap(),
+            _ => Query::And(conjunction),
+        })
+    }
+
+    // Parses a term and returns its index in `KINDS`.
+    fn term(term: &str) -> Result<(usize, Self), QueryError> {
+        let (prefix, arg) = match term.split_once(':') {
+            Some((prefix, arg)) if KINDS.contains(&prefix) => (prefix, arg),
+            _ => ("acct", term.strip_prefix('/').and_then(|t| t.strip_suffix('/')).unwrap_or(term)),
+        };
+        let kind = KINDS.iter().position(|k| *k == prefix).unwrap();
+
+        let query = match prefix {
+            "acct" => Query::Account(regex(arg, term)?),
+            "desc" => Query::Description(regex(arg, term)?),
+            "amt" => Query::Amount(AmountCondition::parse(arg).ok_or_else(|| QueryError::Amount(term.to_owned()))?),
+            "date" => Query::Date(DateSpan::parse(arg).ok_or_else(|| QueryError::Date(term.to_owned()))?),
+            "status" => Query::Status(match arg {
+                "*" => Status::Cleared,
+                "!" => Status::Pending,
+                "" => Status::Uncleared,
+                _ => return Err(QueryError::Status(term.to_owned())),
+            }),
+            "tag" => match arg.split_once('=') {
+                Some((name, value)) => Query::Tag(regex(&format!("^(?:{})$", name), term)?, Some(regex(value, term)?)),
+                None => Query::Tag(regex(&format!("^(?:{})$", arg), term)?, None),
+            },
+            "cur" => Query::Commodity(regex(&format!("^(?:{})$", arg), term)?),
+            _ => unreachable!(),
+        };
+
+        Ok((kind, query))
+    }
+
+    /// Returns true if a posting of `tx` matches, taking its date as the
+    /// date given to the posting or the transaction.
+    pub fn matches(&self, tx: &Transaction, posting: &Posting) -> bool {
+        self.matches_at(tx, posting, posting.date.unwrap_or(tx.header.date))
+    }
+
+    /// Returns true if a posting of `tx` on `date` matches.
+    pub fn matches_at(&self, tx: &Transaction, posting: &Posting, date: NaiveDate) -> bool {
+        match self {
+            Query::Any => true,
+            Query::Account(re) => re.is_match(posting.account),
+            Query::Description(re) => re.is_match(tx.header.description.trim_end()),
+            Query::Amount(cond) => posting.amount.as_ref().is_some_and(|a| cond.matches(a.price)),
+            Query::Date(span) => span.contains(date),
+            Query::Status(status) => tx.header.status == *status,
+            Query::Tag(name, value) => posting.tags.iter().any(|(n, v)| {
+                name.is_match(n) && value.as_ref().is_none_or(|re| v.is_some_and(|v| re.is_match(&v.to_string())))
+            }),
+            Query::Commodity(re) => posting.amount.as_ref().is_some_and(|a| re.is_match(a.unit)),
+            Query::Not(query) => !query.matches_at(tx, posting, date),
+            Query::And(queries) => queries.iter().all(|q| q.matches_at(tx, posting, date)),
+            Query::Or(queries) => queries.iter().any(|q| q.matches_at(tx, posting, date)),
+        }
+    }
+
+    /// Returns true if any posting of `tx` matches.
+    pub fn matches_transaction(&self, tx: &Transaction) -> bool {
+        tx.posting.iter().any(|p| self.matches(tx, p))
+    }
+
+    /// Matches the account and commodity terms against the names in the
+    /// tables once, so that postings are matched by their ids.
+    pub fn resolve(&self, accounts: &AccountTable, commodities: &CommodityTable) -> ResolvedQuery<'_> {
+        match self {
+            Query::Account(re) => ResolvedQuery::Account(re, accounts.iter().map(|(_, name)| re.is_match(name)).collect()),
+            Query::Commodity(re) => ResolvedQuery::Commodity(re, commodities.iter().map(|(_, unit)| re.is_match(unit)).collect()),
+            Query::Not(query) => ResolvedQuery::Not(Box::new(query.resolve(accounts, commodities))),
+            Query::And(queries) => ResolvedQuery::And(queries.iter().map(|q| q.resolve(accounts, commodities)).collect()),
+            Query::Or(queries) => ResolvedQuery::Or(queries.iter().map(|q| q.resolve(accounts, commodities)).collect()),
+            query => ResolvedQuery::Term(query),
+        }
+    }
+}
+
+/// Query resolved against the tables of a journal by `Query::resolve`.
+///
+/// The account and commodity terms have whether they match each id of the
+/// tables. Names which are not in the tables are matched by the regular
+/// expressions.
+#[derive(Debug, Clone)]
+pub enum ResolvedQuery<'q> {
+    Account(&'q Regex, Vec<bool>),
+    Commodity(&'q Regex, Vec<bool>),
+    /// Term which does not depend on the names
+    Term(&'q Query),
+    Not(Box<ResolvedQuery<'q>>),
+    And(Vec<ResolvedQuery<'q>>),
+    Or(Vec<ResolvedQuery<'q>>),
+}
+
+impl<'q> ResolvedQuery<'q> {
+    /// Returns true if a posting of `tx` with `ids` on `date` matches.
+    pub fn matches_at(&self, tx: &Transaction, posting: &Posting, ids: &PostingIds, date: NaiveDate) -> bool {
+        match self {
+            ResolvedQuery::Account(re, matched) => matched.get(ids.account.index())
+                .copied()
+                .unwrap_or_else(|| re.is_match(posting.account)),
+            ResolvedQuery::Commodity(re, matched) => posting.amount.as_ref().is_some_and(|a| {
+                ids.commodity
+                    .and_then(|id| matched.get(id.index()).copied())
+                    .unwrap_or_else(|| re.is_match(a.unit))
+            }),
+            ResolvedQuery::Term(query) => query.matches_at(tx, posting, date),
+            ResolvedQuery::Not(query) => !query.matches_at(tx, posting, ids, date),
+            ResolvedQuery::And(queries) => queries.iter().all(|q| q.matches_at(tx, posting, ids, date)),
+            ResolvedQuery::Or(queries) => queries.iter().any(|q| q.matches_at(tx, posting, ids, date)),
+        }
+    }
+}
+
+// Regular expressions are compared by their patterns.
+impl PartialEq for Query {
+    fn eq(&self, other: &Self) -> bool {
+        let same = |a: &Regex, b: &Regex| a.as_str() == b.as_str();
+
+        match (self, other) {
+            (Query::Any, Query::Any) => true,
+            (Query::Account(a), Query::Account(b)) |
+            (Query::Description(a), Query::Description(b)) |
+            (Query::Commodity(a), Query::Commodity(b)) => same(a, b),
+            (Query::Amount(a), Query::Amount(b)) => a == b,
+            (Query::Date(a), Query::Date(b)) => a == b,
+            (Query::Status(a), Query::Status(b)) => a == b,
+            (Query::Tag(a, x), Query::Tag(b, y)) => {
+                same(a, b) && match (x, y) {
+                    (Some(x), Some(y)) => same(x, y),
+                    (x, y) => x.is_none() && y.is_none(),
+                }
+            },
+            (Query::Not(a), Query::Not(b)) => a == b,
+            (Query::And(a), Query::And(b)) | (Query::Or(a), Query::Or(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+// Splits a line into terms at spaces outside quotes.
+fn split_terms(s: &str) -> Result<Vec<String>, QueryError> {
+    let mut terms = Vec::new();
+    let mut term = String::new();
+    let mut quote = None;
+
+    for c in s.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => term.push(c),
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c.is_whitespace() => {
+                if !term.is_empty() {
+                    terms.push(std::mem::take(&mut term));
+                }
+            },
+            None => term.push(c),
+        }
+    }
+
+    if quote.is_some() {
+        return Err(QueryError::UnclosedQuote(s.to_owned()));
+    }
+    if !term.is_empty() {
+        terms.push(term);
+    }
+
+    Ok(terms)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::journal::Journal;
+
+    const LEDGER: &str = r#"2021-09-16 * Amazon
+    費用:日用品         6000 JPY ; project: 引っ越し
+    負債:クレジットカード
+
+2021-09-20 ! Tomod's
+    費用:食費           500 JPY
+    費用:日用品         10 USD
+    資産:現金           -500 JPY
+    資産:外貨
+"#;
+
+    // Returns the accounts of the postings matching a query
+    fn matched(query: &str) -> Vec<String> {
+        let journal = Journal::parse(LEDGER, "a.ledger").unwrap();
+        let query = &Query::parse(query).unwrap();
+
+        journal.entries.iter()
+            .map(|entry| &entry.transaction)
+            .flat_map(|tx| tx.posting.iter().filter(move |p| query.matches(tx, p)))
+            .map(|p| format!("{} {}", p.account, p.amount.as_ref().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn match_terms() {
+        assert_eq!(matched("acct:^費用 amt:>5000"), vec!["費用:日用品 6000 JPY"]);
+        assert_eq!(matched("desc:amazon"), vec!["費用:日用品 6000 JPY", "負債:クレジットカード -6000 JPY"]);
+        assert_eq!(matched("amt:<=-500"), vec!["負債:クレジットカード -6000 JPY", "資産:現金 -500 JPY"]);
+        assert_eq!(matched("amt:<20"), vec!["費用:日用品 10 USD", "資産:外貨 -10 USD"]);
+        assert_eq!(matched("date:2021-09-20 status:! cur:USD"), vec!["費用:日用品 10 USD", "資産:外貨 -10 USD"]);
+        assert_eq!(matched("tag:project=引っ越し"), vec!["費用:日用品 6000 JPY"]);
+        assert_eq!(matched("資産 not:cur:JPY"), vec!["資産:外貨 -10 USD"]);
+        assert_eq!(matched("/^費用:食/"), vec!["費用:食費 500 JPY"]);
+    }
+
+    #[test]
+    fn match_resolved_terms_by_ids() {
+        let journal = Journal::parse(LEDGER, "a.ledger").unwrap();
+
+        for s in ["acct:^費用 amt:>5000", "資産 not:cur:JPY", "cur:usd 食費", "not:acct:日用品"] {
+            let query = Query::parse(s).unwrap();
+            let resolved = query.resolve(&journal.account_table, &journal.commodity_table);
+            for entry in journal.entries.iter() {
+                let tx = &entry.transaction;
+                for (p, ids) in tx.posting.iter().zip(entry.posting_ids.iter()) {
+                    let date = p.date.unwrap_or(tx.header.date);
+                    assert_eq!(resolved.matches_at(tx, p, ids, date), query.matches(tx, p), "{}", s);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn combine_terms_of_same_kind() {
+        assert_eq!(matched("食費 現金"), vec!["費用:食費 500 JPY", "資産:現金 -500 JPY"]);
+        assert_eq!(matched("'desc:Tomod s' 食費").len(), 0);
+    }
+
+    #[test]
+    fn compare_queries_by_pattern() {
+        assert_eq!(Query::parse("/^費用/ amt:>5000").unwrap(), Query::parse("acct:^費用 amt:>5000").unwrap());
+        assert_ne!(Query::parse("desc:費用").unwrap(), Query::parse("acct:費用").unwrap());
+    }
+
+    #[test]
+    fn reject_invalid_terms() {
+        assert!(matches!(Query::parse("amt:>x"), Err(QueryError::Amount(_))));
+        assert!(matches!(Query::parse("date:2021-13"), Err(QueryError::Date(_))));
+        assert!(matches!(Query::parse("status:?"), Err(QueryError::Status(_))));
+        assert!(matches!(Query::parse("acct:("), Err(QueryError::Regex { .. })));
+        assert!(matches!(Query::parse("desc:'Tomod"), Err(QueryError::UnclosedQuote(_))));
+    }
+}
@@ -0,0 +1,321 @@
+use crate::intern::{AccountId, AccountTable};
+use crate::journal::Journal;
+use crate::report::{pad_left, Balance, ReportOptions, Valuer};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+const AMOUNT_WIDTH: usize = 20;
+
+/// Per-account totals shown as an account tree.
+///
+/// Each account is split into its segments on `:` and every parent account
+/// shows the subtotal of its descendants.
+#[derive(Debug)]
+pub struct BalanceReport<'j, 'a> {
+    accounts: &'j AccountTable<'a>,
+    // Totals of each account including its descendants
+    totals: HashMap<AccountId, Balance<'a>>,
+    // Accounts which have postings of their own
+    posted: HashSet<AccountId>,
+    total: Balance<'a>,
+    // Total book value of the postings if they are valued
+    book: Option<Balance<'a>>,
+}
+
+impl<'j, 'a> BalanceReport<'j, 'a> {
+    /// Accumulates the postings selected by `options`.
+    ///
+    /// Amounts are converted at market prices if `options.value` is given,
+    /// and then the book value of the postings is also totaled.
+    pub fn new(journal: &'j Journal<'a>, options: &ReportOptions) -> Self {
+        let mut report = Self {
+            accounts: &journal.account_table,
+            totals: HashMap::new(),
+            posted: HashSet::new(),
+            total: Balance::new(),
+            book: None,
+        };
+        let postings = options.select(journal, &journal.entries);
+        let valuer = Valuer::new(journal, options, postings.iter().map(|s| s.date));
+
+        if options.value.is_some() {
+            report.book = Some(Balance::new());
+        }
+
+        for s in postings {
+            if let (Some(amount), Some(id)) = (&s.posting.amount, s.ids.commodity) {
+                let (id, value) = valuer.value(id, amount, s.date);
+                let mut balance = Balance::new();
+                balance.add(id, &value);
+                report.add(s.ids.account, &balance);
+            }
+            if let (Some(book), Some((id, value))) = (&mut report.book, valuer.book_value(s.posting, &s.ids, s.date)) {
+                book.add(id, &value);
+            }
+        }
+
+        report
+    }
+
+    /// Adds `balance` to the account `id` and its parents.
+    pub fn add(&mut self, id: AccountId, balance: &Balance<'a>) {
+        self.posted.insert(id);
+        self.total.add_balance(balance);
+        for ancestor in self.accounts.ancestors(id) {
+            self.totals.entry(ancestor).or_default().add_balance(balance);
+        }
+    }
+
+    /// Returns the grand total of the report.
+    pub fn total(&self) -> &Balance<'a> {
+        &self.total
+    }
+
+    /// Returns the total book value of the postings, which is only taken
+    /// if they are valued at market prices.
+    pub fn book_value(&self) -> Option<&Balance<'a>> {
+        self.book.as_ref()
+    }
+
+    /// Returns the grand total less the book value.
+    pub fn unrealized_gain(&self) -> Option<Balance<'a>> {
+        let mut gain = self.total.clone();
+        gain.sub_balance(self.book.as_ref()?);
+        Some(gain)
+    }
+
+    fn is_empty(&self, id: AccountId) -> bool {
+        self.totals.get(&id).is_none_or(|total| total.is_zero()) &&
+            self.accounts.children(id).iter().all(|&child| self.is_empty(child))
+    }
+
+    // Returns the accounts to show among `ids`, sorted by name.
+    fn visible(&self, ids: impl Iterator<Item = AccountId>) -> Vec<AccountId> {
+        let mut visible: Vec<_> = ids.filter(|&id| !self.is_empty(id)).collect();
+        visible.sort_by_key(|&id| self.accounts.leaf(id));
+        visible
+    }
+
+    // Follows the chain of accounts which have no postings and only one
+    // child, so that they are shown in a single line like ledger does.
+    fn collapse(&self, id: AccountId) -> (String, AccountId) {
+        let mut name = self.accounts.leaf(id).to_owned();
+        let mut node = id;
+
+        loop {
+            let children = self.visible(self.accounts.children(node).iter().copied());
+            match children[..] {
+                [child] if !self.posted.contains(&node) => {
+                    name.push(':');
+                    name.push_str(self.accounts.leaf(child));
+                    node = child;
+                },
+                _ => break,
+            }
+        }
+
+        (name, node)
+    }
+
+    fn fmt_accounts(&self, f: &mut fmt::Formatter, ids: Vec<AccountId>, depth: usize) -> fmt::Result {
+        for id in ids {
+            let (name, node) = self.collapse(id);
+            let lines = self.totals.get(&node).map_or_else(|| vec!["0".to_owned()], |t| t.lines());
+            let (last, init) = lines.split_last().unwrap();
+
+            for line in init {
+                writeln!(f, "{}", pad_left(line, AMOUNT_WIDTH))?;
+            }
+            writeln!(f, "{}  {}{}", pad_left(last, AMOUNT_WIDTH), "  ".repeat(depth), name)?;
+
+            let children = self.visible(self.accounts.children(node).iter().copied());
+            self.fmt_accounts(f, children, depth + 1)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Writes the amounts of a balance with a label on the last line.
+fn write_labeled(f: &mut fmt::Formatter, balance: &Balance, label: &str) -> fmt::Result {
+    let lines = balance.lines();
+    let (last, init) = lines.split_last().unwrap();
+
+    for line in init {
+        writeln!(f, "{}", pad_left(line, AMOUNT_WIDTH))?;
+    }
+    writeln!(f, "{}  {}", pad_left(last, AMOUNT_WIDTH), label)
+}
+
+impl<'j, 'a> fmt::Display for BalanceReport<'j, 'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_accounts(f, self.visible(self.accounts.roots()), 0)?;
+
+        writeln!(f, "{}", "-".repeat(AMOUNT_WIDTH))?;
+        for line in self.total().lines() {
+            writeln!(f, "{}", pad_left(&line, AMOUNT_WIDTH))?;
+        }
+        if let (Some(book), Some(gain)) = (self.book_value(), self.unrealized_gain()) {
+            write_labeled(f, book, "Book value")?;
+            write_labeled(f, &gain, "Unrealized gain")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::report::{ValueDate, Valuation};
+    use chrono::NaiveDate;
+    use crate::query::Query;
+
+    fn ymd(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    const LEDGER: &str = r#"2021-01-01 * 開始残高
+    純資産:元入金               -10000 JPY
+    資産:普通預金:JP            8000 JPY
+    資産:現金                   2000 JPY
+
+2021-09-16 * 引き出し
+    資産:現金           1000 JPY
+    資産:普通預金:JP
+
+2021-09-20 * Tomod's
+    費用:食費           500 JPY
+    費用:消耗品費       1000 JPY
+    費用:消耗品費       10 USD
+    資産:現金
+"#;
+
+    #[test]
+    fn show_account_tree() {
+        let journal = Journal::parse(LEDGER, "a.ledger").unwrap();
+        let report = BalanceReport::new(&journal, &ReportOptions::default());
+
+        assert_eq!(
+            report.to_string(),
+            r#"          -10000 JPY  純資産:元入金
+            1500 JPY
+              10 USD  費用
+            1000 JPY
+              10 USD    消耗品費
+             500 JPY    食費
+            8500 JPY
+             -10 USD  資産
+            7000 JPY    普通預金:JP
+            1500 JPY
+             -10 USD    現金
+--------------------
+                   0
+"#
+        );
+    }
+
+    const HOLDINGS: &str = r#"2021-09-01 * 買付
+    資産:証券:VTI       10 VTI @ 12300 JPY
+    資産:証券:USD       1 USD @ 110 JPY
+    資産:普通預金
+
+P 2021-09-16 VTI 22400 JPY
+P 2021-09-16 USD 112 JPY
+
+2021-09-20 * 買付
+    資産:証券:VTI       1 VTI @ 200 USD
+    資産:証券:USD
+"#;
+
+    #[test]
+    fn show_market_value() {
+        let journal = Journal::parse(HOLDINGS, "a.ledger").unwrap();
+        let value = |date| ReportOptions {
+            value: Some(Valuation { commodity: "JPY".to_owned(), date }),
+            ..ReportOptions::default()
+        };
+
+        // Every transaction balances, so the book value of the whole journal
+        // is zero and its market value is all unrealized gain
+        let report = BalanceReport::new(&journal, &value(ValueDate::End));
+        assert_eq!(report.total().lines(), vec!["101002 JPY"]);
+        assert!(report.book_value().unwrap().is_zero());
+        assert_eq!(report.unrealized_gain().unwrap().lines(), vec!["101002 JPY"]);
+        assert_eq!(
+            BalanceReport::new(&journal, &value(ValueDate::Transaction)).total().lines(),
+            vec!["0"]
+        );
+
+        // Valued at the last day of the period, before the market prices
+        let mut options = value(ValueDate::End);
+        options.period.span.end = Some(ymd(2021, 9, 16));
+        assert_eq!(BalanceReport::new(&journal, &options).total().lines(), vec!["0"]);
+        assert_eq!(
+            BalanceReport::new(&journal, &value(ValueDate::At(ymd(2021, 9, 10)))).to_string(),
+            r#"           -9700 JPY  資産
+         -123110 JPY    普通預金
+          113410 JPY    証券
+          -21890 JPY      USD
+          135300 JPY      VTI
+--------------------
+           -9700 JPY
+                   0  Book value
+           -9700 JPY  Unrealized gain
+"#
+        );
+    }
+
+    #[test]
+    fn show_book_value_and_unrealized_gain() {
+        let src = "2021-09-01 * 買付\n    資産:証券:VTI  1 VTI @ 12300 JPY\n    資産:普通預金\n\nP 2021-09-16 VTI 22400 JPY\n";
+        let journal = Journal::parse(src, "a.ledger").unwrap();
+        let options = ReportOptions {
+            query: Query::parse("証券").unwrap(),
+            value: Some(Valuation { commodity: "JPY".to_owned(), date: ValueDate::At(ymd(2021, 9, 16)) }),
+            ..ReportOptions::default()
+        };
+
+        assert_eq!(
+            BalanceReport::new(&journal, &options).to_string(),
+            r#"           22400 JPY  資産:証券:VTI
+--------------------
+           22400 JPY
+           12300 JPY  Book value
+           10100 JPY  Unrealized gain
+"#
+        );
+        assert_eq!(BalanceReport::new(&journal, &ReportOptions::default()).book_value(), None);
+    }
+
+    #[test]
+    fn hide_virtual_postings() {
+        let src = "2021-09-20 * Tomod's\n    費用:食費  500 JPY\n    資産:現金\n    (予算:食費)  -500 JPY\n";
+        let journal = Journal::parse(src, "a.ledger").unwrap();
+        let options = ReportOptions {
+            real: true,
+            ..ReportOptions::default()
+        };
+
+        assert_eq!(BalanceReport::new(&journal, &ReportOptions::default()).total().lines(), vec!["-500 JPY"]);
+        assert_eq!(BalanceReport::new(&journal, &options).total().lines(), vec!["0"]);
+    }
+
+    #[test]
+    fn filter_accounts() {
+        let journal = Journal::parse(LEDGER, "a.ledger").unwrap();
+        let options = ReportOptions {
+            query: Query::parse("普通預金").unwrap(),
+            ..ReportOptions::default()
+        };
+        let report = BalanceReport::new(&journal, &options);
+
+        assert_eq!(
+            report.to_string(),
+            r#"            7000 JPY  資産:普通預金:JP
+--------------------
+            7000 JPY
+"#
+        );
+    }
+}
@@ -0,0 +1,304 @@
+use crate::intern::{AccountId, AccountTable};
+use crate::journal::{Entry, Journal};
+use crate::period::{Interval, Period};
+use crate::report::{write_row, Balance, ReportOptions, Valuer};
+use rust_decimal::Decimal;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use unicode_width::UnicodeWidthStr;
+
+const AMOUNT_WIDTH: usize = 12;
+const PERCENT_WIDTH: usize = 5;
+
+/// Actual and budgeted totals of an account in an interval.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BudgetRow<'a> {
+    pub actual: Balance<'a>,
+    pub budget: Balance<'a>,
+}
+
+impl<'a> BudgetRow<'a> {
+    /// Returns the actual total minus the budget.
+    pub fn difference(&self) -> Balance<'a> {
+        let mut difference = self.actual.clone();
+        difference.sub_balance(&self.budget);
+        difference
+    }
+
+    /// Returns the actual total in percent of the budget, or `None` unless
+    /// the budget is in a single commodity and nothing else is spent.
+    pub fn percentage(&self) -> Option<Decimal> {
+        let budget: Vec<_> = self.budget.amounts().collect();
+        let budget = match &budget[..] {
+            [budget] => budget,
+            _ => return None,
+        };
+
+        let mut actual = Decimal::ZERO;
+        for amount in self.actual.amounts() {
+            if amount.unit != budget.unit {
+                return None;
+            }
+            actual = amount.price;
+        }
+
+        Some((actual / budget.price * Decimal::ONE_HUNDRED).round())
+    }
+}
+
+/// Totals of the budgeted accounts in an interval, shown as an account
+/// tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetInterval<'j, 'a> {
+    /// Name of the interval like `2021-09`
+    pub label: String,
+    accounts: &'j AccountTable<'a>,
+    /// Totals of each account, including the subtotals of its descendants
+    pub rows: HashMap<AccountId, BudgetRow<'a>>,
+}
+
+/// Actual totals compared with the budget given by periodic transactions
+/// in each interval of a period.
+///
+/// Only the accounts with a budget and their parents are shown. A parent
+/// account shows the totals of all its descendants.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BudgetReport<'j, 'a> {
+    pub intervals: Vec<BudgetInterval<'j, 'a>>,
+}
+
+impl<'j, 'a> BudgetReport<'j, 'a> {
+    /// Compares the postings selected by `options` with the ones of the
+    /// `budget` entries forecast by `Journal::forecast`.
+    ///
+    /// The totals are taken in each month unless `options.period` has an
+    /// interval. Amounts are converted at market prices if `options.value`
+    /// is given.
+    pub fn new(journal: &'j Journal<'a>, budget: &[Entry<'a>], options: &ReportOptions) -> Self {
+        let interval = options.period.interval.unwrap_or(Interval::Monthly);
+        let options = &ReportOptions {
+            period: Period {
+                interval: Some(interval),
+                ..options.period
+            },
+            ..options.clone()
+        };
+        let actual = options.select(journal, &journal.entries);
+        let budget = options.select(journal, budget);
+        let valuer = Valuer::new(journal, options, actual.iter().chain(budget.iter()).map(|s| s.date));
+
+        let spans = options.intervals(actual.iter().chain(budget.iter()).map(|s| s.date))
+            .unwrap_or_default();
+        let mut rows = vec![HashMap::new(); spans.len()];
+
+        let postings = actual.iter().map(|s| (false, s)).chain(budget.iter().map(|s| (true, s)));
+        for (budgeted, s) in postings {
+            let (id, amount) = match (&s.posting.amount, s.ids.commodity) {
+                (Some(amount), Some(id)) => valuer.value(id, amount, s.date),
+                _ => continue,
+            };
+            if let Some(i) = spans.iter().position(|span| span.contains(s.date)) {
+                for account in journal.account_table.ancestors(s.ids.account) {
+                    let row: &mut BudgetRow = rows[i].entry(account).or_default();
+                    if budgeted {
+                        row.budget.add(id, &amount);
+                    } else {
+                        row.actual.add(id, &amount);
+                    }
+                }
+            }
+        }
+
+        let accounts = &journal.account_table;
+        let intervals = spans.iter()
+            .zip(rows)
+            .map(|(span, mut rows)| {
+                let budgeted: HashSet<_> = rows.iter()
+                    .filter(|(_, row)| !row.budget.is_zero())
+                    .flat_map(|(&id, _)| accounts.ancestors(id))
+                    .collect();
+                rows.retain(|id, _| budgeted.contains(id));
+                BudgetInterval {
+                    label: interval.label(span.begin.unwrap()),
+                    accounts,
+                    rows,
+                }
+            })
+            .filter(|interval| !interval.rows.is_empty())
+            .collect();
+
+        Self {
+            intervals,
+        }
+    }
+}
+
+impl<'j, 'a> BudgetInterval<'j, 'a> {
+    // Returns the accounts with a row among `ids`, sorted by name.
+    fn visible(&self, ids: &[AccountId]) -> Vec<AccountId> {
+        let mut visible: Vec<_> = ids.iter().copied().filter(|id| self.rows.contains_key(id)).collect();
+        visible.sort_by_key(|&id| self.accounts.leaf(id));
+        visible
+    }
+
+    // Appends the cells of the accounts `ids` and their descendants, with
+    // the names indented by `depth`.
+    fn push_rows(&self, ids: &[AccountId], depth: usize, rows: &mut Vec<(String, Vec<Vec<String>>)>) {
+        for id in self.visible(ids) {
+            let row = &self.rows[&id];
+            let name = format!("{}{}", "  ".repeat(depth), self.accounts.leaf(id));
+            let percentage = row.percentage().map_or_else(String::new, |p| format!("{}%", p));
+            rows.push((name, vec![row.actual.lines(), row.budget.lines(), row.difference().lines(), vec![percentage]]));
+
+            self.push_rows(self.accounts.children(id), depth + 1, rows);
+        }
+    }
+}
+
+impl<'j, 'a> fmt::Display for BudgetInterval<'j, 'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let roots: Vec<_> = self.accounts.roots().collect();
+        let mut rows = Vec::new();
+        self.push_rows(&roots, 0, &mut rows);
+
+        let header = ["Actual", "Budget", "Difference", "%"];
+        let mut widths = vec![rows.iter().map(|(name, _)| name.width()).fold(self.label.width(), usize::max)];
+        for (i, title) in header.iter().enumerate() {
+            let minimum = if i == 3 { PERCENT_WIDTH } else { AMOUNT_WIDTH };
+            let width = rows.iter()
+                .flat_map(|(_, cells)| cells[i].iter())
+                .map(|l| l.width())
+                .fold(title.width().max(minimum), usize::max);
+            widths.push(width);
+        }
+
+        let header: Vec<_> = header.iter().map(|h| vec![h.to_string()]).collect();
+        write_row(f, &self.label, &header, &widths)?;
+        writeln!(f, "{}", "-".repeat(widths[0] + widths[1..].iter().map(|w| w + 2).sum::<usize>()))?;
+        for (name, cells) in rows.iter() {
+            write_row(f, name, cells, &widths)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'j, 'a> fmt::Display for BudgetReport<'j, 'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, interval) in self.intervals.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", interval)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::intern::CommodityTable;
+    use crate::parser::transaction::Amount;
+    use crate::query::Query;
+    use chrono::NaiveDate;
+
+    const LEDGER: &str = r#"~ monthly from 2021-09  生活費
+    費用:住居:家賃      80000 JPY
+    費用:食費           30000 JPY
+    資産:普通預金
+
+2021-09-25 * 家賃
+    費用:住居:家賃      80000 JPY
+    資産:普通預金
+
+2021-09-26 * Tomod's
+    費用:食費           12000 JPY
+    費用:住居:家具      5000 JPY
+    資産:現金
+
+2021-10-05 * Tomod's
+    費用:食費           33000 JPY
+    資産:現金
+"#;
+
+    fn report<'j>(journal: &'j Journal<'static>) -> BudgetReport<'j, 'static> {
+        let ymd = |m, d| NaiveDate::from_ymd_opt(2021, m, d).unwrap();
+        let budget = journal.forecast(ymd(9, 1), ymd(11, 1), ymd(10, 10), 1).unwrap();
+        let options = ReportOptions {
+            query: Query::parse("^費用").unwrap(),
+            ..ReportOptions::default()
+        };
+
+        BudgetReport::new(journal, &budget, &options)
+    }
+
+    #[test]
+    fn compare_with_budget() {
+        let journal = Journal::parse(LEDGER, "a.ledger").unwrap();
+
+        assert_eq!(
+            report(&journal).to_string(),
+            r#"2021-09         Actual        Budget    Difference      %
+---------------------------------------------------------
+費用         97000 JPY    110000 JPY    -13000 JPY    88%
+  住居       85000 JPY     80000 JPY      5000 JPY   106%
+    家賃     80000 JPY     80000 JPY             0   100%
+  食費       12000 JPY     30000 JPY    -18000 JPY    40%
+
+2021-10         Actual        Budget    Difference      %
+---------------------------------------------------------
+費用         33000 JPY    110000 JPY    -77000 JPY    30%
+  住居               0     80000 JPY    -80000 JPY     0%
+    家賃             0     80000 JPY    -80000 JPY     0%
+  食費       33000 JPY     30000 JPY      3000 JPY   110%
+"#
+        );
+    }
+
+    #[test]
+    fn nest_accounts_by_segment() {
+        let src = r#"~ monthly from 2021-09  生活費
+    費用:Food:Rest      10000 JPY
+    費用:Food2021       20000 JPY
+    資産:普通預金
+
+2021-09-25 * 外食
+    費用:Food:Rest      8000 JPY
+    資産:現金
+"#;
+        let journal = Journal::parse(src, "a.ledger").unwrap();
+
+        assert_eq!(
+            report(&journal).intervals[0].to_string(),
+            r#"2021-09           Actual        Budget    Difference      %
+-----------------------------------------------------------
+費用            8000 JPY     30000 JPY    -22000 JPY    27%
+  Food          8000 JPY     10000 JPY     -2000 JPY    80%
+    Rest        8000 JPY     10000 JPY     -2000 JPY    80%
+  Food2021             0     20000 JPY    -20000 JPY     0%
+"#
+        );
+    }
+
+    #[test]
+    fn show_percentage_in_single_commodity() {
+        let row = |actual: &[(&'static str, &'static str)], budget: &[(&'static str, &'static str)]| {
+            let mut commodities = CommodityTable::new();
+            let mut row = BudgetRow::default();
+            for (price, unit) in actual {
+                row.actual.add(commodities.intern(unit), &Amount::from_str(price, unit).unwrap());
+            }
+            for (price, unit) in budget {
+                row.budget.add(commodities.intern(unit), &Amount::from_str(price, unit).unwrap());
+            }
+            row.percentage()
+        };
+
+        assert_eq!(row(&[("250", "JPY")], &[("1000", "JPY")]), Some(25.into()));
+        assert_eq!(row(&[], &[("1000", "JPY")]), Some(0.into()));
+        assert_eq!(row(&[("10", "USD")], &[("1000", "JPY")]), None);
+        assert_eq!(row(&[], &[("1000", "JPY"), ("10", "USD")]), None);
+    }
+}
@@ -0,0 +1,343 @@
+pub mod balance;
+pub mod budget;
+pub mod multi_balance;
+pub mod print;
+pub mod register;
+
+use crate::intern::{CommodityId, PostingIds};
+use crate::journal::{Entry, Journal};
+use crate::parser::transaction::{Amount, Posting, PostingKind, Transaction, TransactionHeader};
+use crate::period::{DateSpan, Period};
+use crate::price::PriceDb;
+use crate::query::Query;
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::fmt;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Options shared by the reports.
+#[derive(Debug, Default, Clone)]
+pub struct ReportOptions {
+    /// Query to select postings.
+    pub query: Query,
+    /// Use the effective dates of transactions if they have one.
+    pub effective: bool,
+    /// Convert amounts to a commodity at market prices.
+    pub value: Option<Valuation>,
+    /// Leave out virtual postings.
+    pub real: bool,
+    /// Span of dates to report and the intervals to divide it into.
+    pub period: Period,
+}
+
+/// Date of the prices at which amounts are valued.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValueDate {
+    /// The date of each transaction
+    Transaction,
+    /// The last day of the period, or the date of the last posting
+    /// selected if the period has no end
+    End,
+    /// A fixed date such as today
+    At(NaiveDate),
+}
+
+/// Conversion of amounts to a commodity in reports.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Valuation {
+    pub commodity: String,
+    pub date: ValueDate,
+}
+
+/// Posting selected for a report.
+#[derive(Debug, Clone, Copy)]
+pub struct Selected<'t, 'a> {
+    /// Date of the posting used in reports
+    pub date: NaiveDate,
+    /// Index of the entry in the entries selected from
+    pub index: usize,
+    pub tx: &'t Transaction<'a>,
+    pub posting: &'t Posting<'a>,
+    pub ids: PostingIds,
+}
+
+impl ReportOptions {
+    /// Selects the postings of `entries` in their order, matching the query
+    /// by the ids of the postings.
+    ///
+    /// The ids are resolved against the tables of `journal`. Postings
+    /// without ids are left out.
+    pub fn select<'t, 'a>(&self, journal: &Journal<'a>, entries: &'t [Entry<'a>]) -> Vec<Selected<'t, 'a>> {
+        let query = self.query.resolve(&journal.account_table, &journal.commodity_table);
+
+        entries.iter()
+            .enumerate()
+            .flat_map(|(index, entry)| {
+                let tx = &entry.transaction;
+                tx.posting.iter().zip(entry.posting_ids.iter()).map(move |(posting, ids)| Selected {
+                    date: self.posting_date(&tx.header, posting),
+                    index,
+                    tx,
+                    posting,
+                    ids: *ids,
+                })
+            })
+            .filter(|s| {
+                (!self.real || s.posting.kind == PostingKind::Real) &&
+                    self.period.span.contains(s.date) &&
+                    query.matches_at(s.tx, s.posting, &s.ids, s.date)
+            })
+            .collect()
+    }
+
+    /// Returns true if a posting of `tx` is selected by the query and its
+    /// kind.
+    pub fn matches_posting(&self, tx: &Transaction, posting: &Posting) -> bool {
+        let date = self.posting_date(&tx.header, posting);
+        (!self.real || posting.kind == PostingKind::Real) &&
+            self.period.span.contains(date) &&
+            self.query.matches_at(tx, posting, date)
+    }
+
+    /// Returns true if any posting of `tx` is selected.
+    pub fn matches_transaction(&self, tx: &Transaction) -> bool {
+        tx.posting.iter().any(|p| self.matches_posting(tx, p))
+    }
+
+    /// Returns the date of a transaction used in reports.
+    pub fn date(&self, header: &TransactionHeader) -> NaiveDate {
+        match header.edate {
+            Some(edate) if self.effective => edate,
+            _ => header.date,
+        }
+    }
+
+    /// Returns the date of a posting used in reports.
+    ///
+    /// The dates given to the posting take precedence over the ones of its
+    /// transaction.
+    pub fn posting_date(&self, header: &TransactionHeader, posting: &Posting) -> NaiveDate {
+        let date = posting.date.unwrap_or(header.date);
+        if self.effective {
+            posting.edate.or(header.edate).unwrap_or(date)
+        } else {
+            date
+        }
+    }
+
+    /// Returns the intervals of the period covering `dates`, or `None` if
+    /// the period has no interval.
+    ///
+    /// An open side of the period is bounded by the earliest or the latest
+    /// of `dates`.
+    pub fn intervals(&self, dates: impl Iterator<Item = NaiveDate> + Clone) -> Option<Vec<DateSpan>> {
+        let interval = self.period.interval?;
+        let begin = self.period.span.begin.or_else(|| dates.clone().min());
+        let end = self.period.span.end.or_else(|| dates.max().and_then(|d| d.succ_opt()));
+
+        Some(match (begin, end) {
+            (Some(begin), Some(end)) => interval.split(begin, end),
+            _ => Vec::new(),
+        })
+    }
+}
+
+/// Converts amounts in a journal as requested by `ReportOptions::value`.
+#[derive(Debug, Clone)]
+pub struct Valuer<'j, 'a> {
+    prices: &'j PriceDb<'a>,
+    // Commodity to convert to, or None if amounts are kept as they are
+    commodity: Option<(CommodityId, &'a str)>,
+    date: Option<NaiveDate>,
+}
+
+impl<'j, 'a> Valuer<'j, 'a> {
+    /// Creates a converter for the postings selected by `options`, which
+    /// are posted on `dates`.
+    pub fn new(journal: &'j Journal<'a>, options: &ReportOptions, dates: impl Iterator<Item = NaiveDate>) -> Self {
+        let valuation = options.value.as_ref();
+        let date = valuation.and_then(|v| match v.date {
+            ValueDate::Transaction => None,
+            ValueDate::End => match options.period.span.end {
+                Some(end) => end.pred_opt(),
+                None => dates.max(),
+            },
+            ValueDate::At(date) => Some(date),
+        });
+
+        Self {
+            prices: &journal.prices,
+            commodity: valuation
+                .and_then(|v| journal.prices.commodity(&v.commodity))
+                .and_then(|unit| Some((journal.commodity_table.get(unit)?, unit))),
+            date,
+        }
+    }
+
+    /// Converts `amount` in the commodity `id` posted on `date`, and
+    /// returns it with the id of its commodity.
+    ///
+    /// Amounts in commodities without a price are kept as they are.
+    pub fn value(&self, id: CommodityId, amount: &Amount<'a>, date: NaiveDate) -> (CommodityId, Amount<'a>) {
+        self.convert(id, amount.clone(), self.date.unwrap_or(date))
+    }
+
+    /// Converts the book value of a posting on `date` at the prices of the
+    /// date, or returns `None` if it has no amount in a commodity.
+    ///
+    /// The book value is the cost of the lot if annotated, otherwise the
+    /// price paid for the amount if given, otherwise the amount itself.
+    pub fn book_value(&self, posting: &Posting<'a>, ids: &PostingIds, date: NaiveDate) -> Option<(CommodityId, Amount<'a>)> {
+        let amount = posting.amount.as_ref()?;
+        let lot_cost = posting.lot.as_ref()
+            .and_then(|lot| lot.cost.as_ref())
+            .map(|cost| Amount::new(amount.price * cost.price, cost.unit))
+            .or_else(|| posting.cost.as_ref().map(|cost| cost.total(amount.price)));
+        let (id, cost) = match lot_cost {
+            Some(cost) => (ids.cost?, cost),
+            None => (ids.commodity?, amount.clone()),
+        };
+
+        Some(self.convert(id, cost, date))
+    }
+
+    fn convert(&self, id: CommodityId, amount: Amount<'a>, date: NaiveDate) -> (CommodityId, Amount<'a>) {
+        match self.commodity {
+            Some((to_id, to)) => match self.prices.convert(&amount, to, date) {
+                Some(converted) => (to_id, converted),
+                None => (id, amount),
+            },
+            None => (id, amount),
+        }
+    }
+}
+
+/// Truncates `s` to `width` columns and pads it with spaces on the right.
+///
+/// The width of East Asian wide characters is counted as two columns. A
+/// truncated string ends with `..` unless `width` is too narrow for it.
+pub fn fit(s: &str, width: usize) -> String {
+    let mut fitted = String::new();
+    let mut w = 0;
+
+    if s.width() > width {
+        let ellipsis = if width >= 2 { ".." } else { "" };
+        for c in s.chars() {
+            let cw = c.width().unwrap_or(0);
+            if w + cw + ellipsis.len() > width {
+                break;
+            }
+            fitted.push(c);
+            w += cw;
+        }
+        fitted.push_str(ellipsis);
+        w += ellipsis.len();
+    } else {
+        fitted.push_str(s);
+        w = s.width();
+    }
+
+    fitted.push_str(&" ".repeat(width.saturating_sub(w)));
+    fitted
+}
+
+/// Pads `s` with spaces on the left so that it spans `width` columns.
+pub fn pad_left(s: &str, width: usize) -> String {
+    format!("{}{}", " ".repeat(width.saturating_sub(s.width())), s)
+}
+
+/// Writes a row of a table whose cells have a line per amount.
+///
+/// `widths` has the width of the name followed by the widths of the cells.
+/// The name is written on the first line and the cells are right-aligned.
+pub fn write_row(f: &mut fmt::Formatter, name: &str, cells: &[Vec<String>], widths: &[usize]) -> fmt::Result {
+    let height = cells.iter().map(|c| c.len()).max().unwrap_or(1);
+
+    for line in 0..height {
+        write!(f, "{}", fit(if line == 0 { name } else { "" }, widths[0]))?;
+        for (cell, width) in cells.iter().zip(widths[1..].iter()) {
+            write!(f, "  {}", pad_left(cell.get(line).map_or("", |s| s.as_str()), *width))?;
+        }
+        writeln!(f)?;
+    }
+
+    Ok(())
+}
+
+/// Sum of amounts in several commodities, kept by the ids of the
+/// commodities.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Balance<'a> {
+    amounts: HashMap<CommodityId, Amount<'a>>,
+}
+
+impl<'a> Balance<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `amount` in the commodity `id`.
+    pub fn add(&mut self, id: CommodityId, amount: &Amount<'a>) {
+        self.amounts.entry(id)
+            .or_insert_with(|| Amount::new(Decimal::ZERO, amount.unit))
+            .price += amount.price;
+    }
+
+    pub fn add_balance(&mut self, other: &Balance<'a>) {
+        for (id, amount) in other.amounts.iter() {
+            self.add(*id, amount);
+        }
+    }
+
+    pub fn sub_balance(&mut self, other: &Balance<'a>) {
+        for (id, amount) in other.amounts.iter() {
+            self.add(*id, &Amount::new(-amount.price, amount.unit));
+        }
+    }
+
+    /// Returns true if the amounts in every commodity are zero.
+    pub fn is_zero(&self) -> bool {
+        self.amounts.values().all(|amount| amount.price.is_zero())
+    }
+
+    /// Returns the non-zero amounts with the ids of their commodities,
+    /// sorted by unit.
+    pub fn entries(&self) -> Vec<(CommodityId, &Amount<'a>)> {
+        let mut entries: Vec<_> = self.amounts.iter()
+            .filter(|(_, amount)| !amount.price.is_zero())
+            .map(|(id, amount)| (*id, amount))
+            .collect();
+        entries.sort_by_key(|(_, amount)| amount.unit);
+        entries
+    }
+
+    /// Iterates over the non-zero amounts sorted by unit.
+    pub fn amounts(&self) -> impl Iterator<Item = Amount<'a>> + '_ {
+        self.entries().into_iter().map(|(_, amount)| amount.clone())
+    }
+
+    /// Formats the amounts one per line, or `0` if the balance is zero.
+    pub fn lines(&self) -> Vec<String> {
+        let lines: Vec<_> = self.amounts().map(|a| a.to_string()).collect();
+        if lines.is_empty() {
+            vec!["0".to_owned()]
+        } else {
+            lines
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fit_to_width() {
+        assert_eq!(fit("資産:現金", 12), "資産:現金   ");
+        assert_eq!(fit("資産:普通預金", 8), "資産:.. ");
+        assert_eq!(fit("資産", 1), " ");
+        assert_eq!(fit("abc", 1), "a");
+        assert_eq!(fit("abc", 0), "");
+    }
+}
@@ -0,0 +1,148 @@
+use crate::journal::Journal;
+use crate::report::{write_row, Balance, ReportOptions, Valuer};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use unicode_width::UnicodeWidthStr;
+
+const AMOUNT_WIDTH: usize = 12;
+
+/// Per-account changes in each interval of a period, shown as a table with
+/// a column for each interval.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MultiBalanceReport<'a> {
+    /// Names of the intervals like `2021-09`
+    pub labels: Vec<String>,
+    /// Changes of each account in the intervals
+    pub rows: BTreeMap<&'a str, Vec<Balance<'a>>>,
+    /// Total changes in the intervals
+    pub totals: Vec<Balance<'a>>,
+}
+
+impl<'a> MultiBalanceReport<'a> {
+    /// Accumulates the postings selected by `options` in the intervals of
+    /// `options.period`.
+    ///
+    /// Amounts are converted at market prices if `options.value` is given.
+    /// The report has no columns if the period has no interval.
+    pub fn new(journal: &Journal<'a>, options: &ReportOptions) -> Self {
+        let postings = options.select(journal, &journal.entries);
+        let valuer = Valuer::new(journal, options, postings.iter().map(|s| s.date));
+
+        let spans = options.intervals(postings.iter().map(|s| s.date)).unwrap_or_default();
+        let labels = match options.period.interval {
+            Some(interval) => spans.iter().map(|s| interval.label(s.begin.unwrap())).collect(),
+            None => Vec::new(),
+        };
+        let mut rows = HashMap::new();
+        let mut totals = vec![Balance::new(); spans.len()];
+
+        for s in postings {
+            let (id, amount) = match (&s.posting.amount, s.ids.commodity) {
+                (Some(amount), Some(id)) => valuer.value(id, amount, s.date),
+                _ => continue,
+            };
+            if let Some(i) = spans.iter().position(|span| span.contains(s.date)) {
+                rows.entry(s.ids.account)
+                    .or_insert_with(|| vec![Balance::new(); spans.len()])[i]
+                    .add(id, &amount);
+                totals[i].add(id, &amount);
+            }
+        }
+
+        Self {
+            labels,
+            rows: rows.into_iter()
+                .map(|(id, balances)| (journal.account_table.name(id), balances))
+                .collect(),
+            totals,
+        }
+    }
+}
+
+impl<'a> fmt::Display for MultiBalanceReport<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rows: Vec<(&str, Vec<Vec<String>>)> = self.rows.iter()
+            .map(|(account, balances)| (*account, balances.iter().map(|b| b.lines()).collect()))
+            .collect();
+        let totals: Vec<_> = self.totals.iter().map(|b| b.lines()).collect();
+
+        let mut widths = vec![rows.iter().map(|(account, _)| account.width()).max().unwrap_or(0)];
+        for (i, label) in self.labels.iter().enumerate() {
+            let lines = rows.iter().map(|(_, cells)| &cells[i]).chain(Some(&totals[i]));
+            let width = lines.flatten().map(|l| l.width()).fold(label.width(), usize::max);
+            widths.push(width.max(AMOUNT_WIDTH));
+        }
+        let rule = "-".repeat(widths[0] + widths[1..].iter().map(|w| w + 2).sum::<usize>());
+
+        let labels: Vec<_> = self.labels.iter().map(|l| vec![l.clone()]).collect();
+        write_row(f, "", &labels, &widths)?;
+        writeln!(f, "{}", rule)?;
+        for (account, cells) in rows.iter() {
+            write_row(f, account, cells, &widths)?;
+        }
+        writeln!(f, "{}", rule)?;
+        write_row(f, "", &totals, &widths)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::period::Period;
+    use crate::query::Query;
+    use chrono::NaiveDate;
+
+    const LEDGER: &str = r#"2021-09-16 * 引き出し
+    資産:現金           1000 JPY
+    資産:普通預金
+
+2021-09-20 * Tomod's
+    費用:食費           500 JPY
+    資産:現金
+
+2021-11-25 * ドラッグストア
+    費用:消耗品費       1000 JPY
+    費用:消耗品費       10 USD
+    費用:食費           300 JPY
+    負債:クレジットカード
+"#;
+
+    #[test]
+    fn show_columns_per_month() {
+        let journal = Journal::parse(LEDGER, "a.ledger").unwrap();
+        let today = NaiveDate::from_ymd_opt(2021, 12, 1).unwrap();
+        let options = ReportOptions {
+            query: Query::parse("^費用").unwrap(),
+            period: Period::parse("monthly", today).unwrap(),
+            ..ReportOptions::default()
+        };
+
+        assert_eq!(
+            MultiBalanceReport::new(&journal, &options).to_string(),
+            r#"                    2021-09       2021-10       2021-11
+-------------------------------------------------------
+費用:消耗品費             0             0      1000 JPY
+                                                 10 USD
+費用:食費           500 JPY             0       300 JPY
+-------------------------------------------------------
+                    500 JPY             0      1300 JPY
+                                                 10 USD
+"#
+        );
+    }
+
+    #[test]
+    fn bound_columns_by_period() {
+        let journal = Journal::parse(LEDGER, "a.ledger").unwrap();
+        let today = NaiveDate::from_ymd_opt(2021, 12, 1).unwrap();
+        let options = ReportOptions {
+            period: Period::parse("quarterly from 2021-10 to 2022", today).unwrap(),
+            ..ReportOptions::default()
+        };
+        let report = MultiBalanceReport::new(&journal, &options);
+
+        assert_eq!(report.labels, vec!["2021Q4"]);
+        assert_eq!(report.rows.keys().collect::<Vec<_>>(), vec![&"負債:クレジットカード", &"費用:消耗品費", &"費用:食費"]);
+        assert!(report.totals[0].is_zero());
+    }
+}
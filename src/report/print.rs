@@ -0,0 +1,114 @@
+use crate::parser::transaction::{Transaction, AMOUNT_COLUMN};
+use crate::report::ReportOptions;
+use std::fmt;
+
+/// Transactions printed in the canonical journal syntax.
+#[derive(Debug, Clone)]
+pub struct PrintReport<'a, 'b> {
+    transactions: Vec<&'b Transaction<'a>>,
+    /// Column at which the amounts of postings end
+    pub amount_column: usize,
+}
+
+impl<'a, 'b> PrintReport<'a, 'b> {
+    /// Selects the transactions which have a posting selected by `options`.
+    pub fn new(transactions: &'b [Transaction<'a>], options: &ReportOptions) -> Self {
+        let transactions = transactions.iter()
+            .filter(|tx| options.matches_transaction(tx))
+            .collect();
+
+        Self {
+            transactions,
+            amount_column: AMOUNT_COLUMN,
+        }
+    }
+}
+
+impl<'a, 'b> fmt::Display for PrintReport<'a, 'b> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, tx) in self.transactions.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{:column$}", tx, column = self.amount_column)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::transaction::{Cost, PostingKind};
+    use crate::parser::{LedgerItem, LedgerParser};
+
+    fn parse(src: &str) -> Vec<Transaction<'_>> {
+        LedgerParser::new(src)
+            .filter_map(|item| match item.unwrap() {
+                LedgerItem::Transaction(tx) => Some(tx),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn normalise_journal() {
+        let src = r#"
+2021-09-16 * 引き出し
+  資産:現金 1000 JPY
+	資産:普通預金:JP
+
+
+2021-09-20 * Tomod's
+    費用:食費           500 JPY
+    資産:現金
+"#;
+        let transactions = parse(src);
+        let mut report = PrintReport::new(&transactions, &ReportOptions::default());
+        report.amount_column = 32;
+
+        assert_eq!(
+            report.to_string(),
+            r#"2021-09-16 * 引き出し
+    資産:現金           1000 JPY
+    資産:普通預金:JP
+
+2021-09-20 * Tomod's
+    費用:食費            500 JPY
+    資産:現金
+"#
+        );
+    }
+    #[test]
+    fn parse_printed_journal() {
+        let src = r#"2021-09-16=2021-09-17 * (#100) 買付  ; 定期 :投資:
+    ; 積立分
+    ;   二行目
+    資産:ETF            10 VTI {12000 JPY} [2021-03-01] (NISA) @@ 120000 JPY
+    資産:証券口座       -120000 JPY  ; [2021-09-20=2021-09-21]
+        ; 翌営業日に引き落とし
+
+2021-09-20 ! 引き出し
+    資産:現金           1000 JPY = 1500 JPY
+    資産:普通預金  = 50000 JPY
+    資産:外貨           10 USD @ 110 JPY
+    (予算:食費)         -1000 JPY
+    [予算:残高]         500 JPY
+    [予算:食費]
+"#;
+        let transactions = parse(src);
+        let (bought, withdrawn) = (&transactions[0], &transactions[1]);
+        assert_eq!(bought.header.notes.len(), 2);
+        assert!(bought.posting[0].lot.is_some());
+        assert!(matches!(bought.posting[0].cost, Some(Cost::Total(_))));
+        assert!(bought.posting[1].date.is_some() && bought.posting[1].notes.len() == 1);
+        assert!(withdrawn.posting[1].amount.is_none() && withdrawn.posting[1].assign.is_some());
+        assert_eq!(withdrawn.posting[3].kind, PostingKind::Virtual);
+        assert_eq!(withdrawn.posting[5].kind, PostingKind::BalancedVirtual);
+
+        let printed = PrintReport::new(&transactions, &ReportOptions::default()).to_string();
+
+        assert_eq!(parse(&printed), transactions);
+    }
+}
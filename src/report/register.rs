@@ -0,0 +1,305 @@
+use crate::intern::AccountTable;
+use crate::journal::Journal;
+use crate::parser::transaction::Amount;
+use crate::period::{DateSpan, Interval};
+use crate::report::{fit, pad_left, Balance, ReportOptions, Selected, Valuer};
+use chrono::NaiveDate;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+
+const DATE_WIDTH: usize = 10;
+const DESCRIPTION_WIDTH: usize = 20;
+const ACCOUNT_WIDTH: usize = 22;
+const AMOUNT_WIDTH: usize = 12;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegisterPosting<'a> {
+    pub account: &'a str,
+    pub amount: Amount<'a>,
+    /// Running total after this posting
+    pub total: Balance<'a>,
+}
+
+/// Postings of a transaction selected in a register report, or the
+/// subtotals of an interval.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegisterEntry<'a> {
+    pub date: NaiveDate,
+    /// Description of the transaction or the name of the interval
+    pub description: Cow<'a, str>,
+    pub postings: Vec<RegisterPosting<'a>>,
+}
+
+/// Chronological list of postings with their running total.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RegisterReport<'a> {
+    pub entries: Vec<RegisterEntry<'a>>,
+}
+
+impl<'a> RegisterReport<'a> {
+    /// Lists the postings selected by `options`.
+    ///
+    /// Postings are sorted by their dates, keeping the order in the journal
+    /// for postings on the same day. Consecutive postings of a transaction
+    /// on the same day are grouped into an entry. Amounts are converted at
+    /// market prices if `options.value` is given.
+    ///
+    /// If `options.period` has an interval, each entry has the subtotals of
+    /// the accounts in an interval instead, and intervals without postings
+    /// are left out.
+    pub fn new(journal: &Journal<'a>, options: &ReportOptions) -> Self {
+        let mut postings = options.select(journal, &journal.entries);
+        let valuer = Valuer::new(journal, options, postings.iter().map(|s| s.date));
+        postings.sort_by_key(|s| s.date);
+
+        let entries = match options.intervals(postings.iter().map(|s| s.date)) {
+            Some(spans) => Self::subtotals(&valuer, &journal.account_table, options.period.interval.unwrap(), &spans, &postings),
+            None => Self::entries(&valuer, &postings),
+        };
+
+        Self {
+            entries,
+        }
+    }
+
+    // Groups consecutive postings of a transaction on the same day.
+    fn entries(valuer: &Valuer<'_, 'a>, postings: &[Selected<'_, 'a>]) -> Vec<RegisterEntry<'a>> {
+        let mut total = Balance::new();
+        let mut entries: Vec<RegisterEntry> = Vec::new();
+        let mut last = None;
+
+        for s in postings {
+            let (id, amount) = match (&s.posting.amount, s.ids.commodity) {
+                (Some(amount), Some(id)) => valuer.value(id, amount, s.date),
+                _ => continue,
+            };
+            total.add(id, &amount);
+            let posting = RegisterPosting {
+                account: s.posting.account,
+                amount,
+                total: total.clone(),
+            };
+
+            match entries.last_mut() {
+                Some(entry) if last == Some((s.index, s.date)) => entry.postings.push(posting),
+                _ => entries.push(RegisterEntry {
+                    date: s.date,
+                    description: trim_end(&s.tx.header.description),
+                    postings: vec![posting],
+                }),
+            }
+            last = Some((s.index, s.date));
+        }
+
+        entries
+    }
+
+    // Sums up the postings in each interval by account.
+    fn subtotals(valuer: &Valuer<'_, 'a>, accounts: &AccountTable<'a>, interval: Interval, spans: &[DateSpan], postings: &[Selected<'_, 'a>]) -> Vec<RegisterEntry<'a>> {
+        let mut total = Balance::new();
+        let mut entries = Vec::new();
+        let mut rest = postings;
+
+        for span in spans {
+            let end = rest.iter().position(|s| !span.contains(s.date)).unwrap_or(rest.len());
+            let (postings, next) = rest.split_at(end);
+            rest = next;
+
+            let mut subtotals: HashMap<_, Balance<'a>> = HashMap::new();
+            for s in postings {
+                if let (Some(amount), Some(id)) = (&s.posting.amount, s.ids.commodity) {
+                    let (id, amount) = valuer.value(id, amount, s.date);
+                    subtotals.entry(s.ids.account).or_default().add(id, &amount);
+                }
+            }
+            let mut subtotals: Vec<_> = subtotals.into_iter().collect();
+            subtotals.sort_by_key(|(account, _)| accounts.name(*account));
+
+            let mut entry_postings = Vec::new();
+            for (account, balance) in subtotals.iter() {
+                for (id, amount) in balance.entries() {
+                    total.add(id, amount);
+                    entry_postings.push(RegisterPosting {
+                        account: accounts.name(*account),
+                        amount: amount.clone(),
+                        total: total.clone(),
+                    });
+                }
+            }
+
+            if !entry_postings.is_empty() {
+                let begin = span.begin.unwrap();
+                entries.push(RegisterEntry {
+                    date: begin,
+                    description: Cow::Owned(interval.label(begin)),
+                    postings: entry_postings,
+                });
+            }
+        }
+
+        entries
+    }
+}
+
+// Returns a description without trailing spaces, borrowing the source text
+// if the description does.
+fn trim_end<'a>(description: &Cow<'a, str>) -> Cow<'a, str> {
+    match description {
+        Cow::Borrowed(s) => Cow::Borrowed(s.trim_end()),
+        Cow::Owned(s) => Cow::Owned(s.trim_end().to_owned()),
+    }
+}
+
+impl<'a> fmt::Display for RegisterReport<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let blank = " ".repeat(DATE_WIDTH + DESCRIPTION_WIDTH + 2);
+        let total_indent = " ".repeat(DATE_WIDTH + DESCRIPTION_WIDTH + ACCOUNT_WIDTH + AMOUNT_WIDTH + 4);
+
+        for entry in self.entries.iter() {
+            for (i, posting) in entry.postings.iter().enumerate() {
+                if i == 0 {
+                    write!(
+                        f,
+                        "{} {} ",
+                        entry.date.format("%Y-%m-%d"),
+                        fit(&entry.description, DESCRIPTION_WIDTH)
+                    )?;
+                } else {
+                    write!(f, "{}", blank)?;
+                }
+
+                let lines = posting.total.lines();
+                writeln!(
+                    f,
+                    "{} {} {}",
+                    fit(posting.account, ACCOUNT_WIDTH),
+                    pad_left(&posting.amount.to_string(), AMOUNT_WIDTH),
+                    pad_left(&lines[0], AMOUNT_WIDTH)
+                )?;
+                for line in lines[1..].iter() {
+                    writeln!(f, "{}{}", total_indent, pad_left(line, AMOUNT_WIDTH))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::period::Period;
+    use crate::query::Query;
+
+    const LEDGER: &str = r#"2021-09-20=2021-10-27 * Tomod's
+    費用:食費           500 JPY
+    負債:クレジットカード
+
+2021-09-16 * 引き出し
+    資産:現金           1000 JPY
+    資産:普通預金:JP
+
+2021-09-25 * ドラッグストアで日用品を購入
+    費用:消耗品費       1000 JPY
+    費用:消耗品費       10 USD
+    負債:クレジットカード
+"#;
+
+    #[test]
+    fn list_postings_chronologically() {
+        let journal = Journal::parse(LEDGER, "a.ledger").unwrap();
+        let options = ReportOptions {
+            query: Query::parse("^費用").unwrap(),
+            ..ReportOptions::default()
+        };
+        let report = RegisterReport::new(&journal, &options);
+
+        assert_eq!(
+            report.to_string(),
+            r#"2021-09-20 Tomod's              費用:食費                   500 JPY      500 JPY
+2021-09-25 ドラッグストアで日.. 費用:消耗品費              1000 JPY     1500 JPY
+                                費用:消耗品費                10 USD     1500 JPY
+                                                                          10 USD
+"#
+        );
+    }
+
+    #[test]
+    fn sort_by_posting_date() {
+        let src = r#"2021-09-20 * Tomod's
+    費用:食費           500 JPY
+    負債:クレジットカード ; [=2021-10-27]
+
+2021-09-25 * ドラッグストア
+    費用:消耗品費       1000 JPY
+    負債:クレジットカード
+    ; date: 2021-09-26
+"#;
+        let journal = Journal::parse(src, "a.ledger").unwrap();
+        let dates = |effective| {
+            let options = ReportOptions {
+                effective,
+                ..ReportOptions::default()
+            };
+            RegisterReport::new(&journal, &options).entries.iter()
+                .map(|e| (e.date.to_string(), e.postings.len()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            dates(false),
+            vec![("2021-09-20".to_owned(), 2), ("2021-09-25".to_owned(), 1), ("2021-09-26".to_owned(), 1)]
+        );
+        assert_eq!(
+            dates(true),
+            vec![
+                ("2021-09-20".to_owned(), 1),
+                ("2021-09-25".to_owned(), 1),
+                ("2021-09-26".to_owned(), 1),
+                ("2021-10-27".to_owned(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn sort_by_effective_date() {
+        let journal = Journal::parse(LEDGER, "a.ledger").unwrap();
+        let options = ReportOptions {
+            query: Query::parse("クレジットカード").unwrap(),
+            effective: true,
+            ..ReportOptions::default()
+        };
+        let report = RegisterReport::new(&journal, &options);
+        let dates: Vec<_> = report.entries.iter()
+            .map(|e| e.date.to_string())
+            .collect();
+
+        assert_eq!(dates, vec!["2021-09-25", "2021-10-27"]);
+    }
+
+    #[test]
+    fn show_subtotals_per_interval() {
+        let journal = Journal::parse(LEDGER, "a.ledger").unwrap();
+        let options = ReportOptions {
+            query: Query::parse("^費用").unwrap(),
+            period: Period {
+                interval: Some(Interval::Monthly),
+                ..Period::default()
+            },
+            ..ReportOptions::default()
+        };
+        let report = RegisterReport::new(&journal, &options);
+
+        assert_eq!(
+            report.to_string(),
+            r#"2021-09-01 2021-09              費用:消耗品費              1000 JPY     1000 JPY
+                                費用:消耗品費                10 USD     1000 JPY
+                                                                          10 USD
+                                費用:食費                   500 JPY     1500 JPY
+                                                                          10 USD
+"#
+        );
+    }
+}
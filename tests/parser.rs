@@ -1,6 +1,9 @@
+#![allow(clippy::redundant_static_lifetimes)]
+
 use mini_ledger::parser::{
     LedgerItem,
     LedgerParser,
+    ParseError,
 };
 
 const LEDGER0: &'static str = r#"
@@ -15,8 +18,101 @@ const LEDGER0: &'static str = r#"
 fn test_ledger_parser() {
     let mut parser = LedgerParser::new(LEDGER0);
 
-    assert_eq!(parser.next(), Some(LedgerItem::Blank));
+    assert_eq!(parser.next(), Some(Ok(LedgerItem::Blank)));
 
     let item = parser.next();
     eprintln!("{:?}", item);
+    assert!(matches!(item, Some(Ok(LedgerItem::Transaction(_)))));
+    assert_eq!(parser.next(), None);
+}
+
+const LEDGER_BAD_DATE: &str = r#"2021-09-16 * 引き出し
+    資産:現金           1000 JPY
+    資産:普通預金:JP    -1000 JPY
+
+2021-09-31 * Tomod's
+    費用:食費           500 JPY
+    資産:現金
+"#;
+
+#[test]
+fn test_ledger_parser_error() {
+    let mut parser = LedgerParser::with_filename(LEDGER_BAD_DATE, "2021.ledger");
+
+    assert!(matches!(parser.next(), Some(Ok(LedgerItem::Transaction(_)))));
+    assert_eq!(parser.next(), Some(Ok(LedgerItem::Blank)));
+
+    let e = parser.next().unwrap().unwrap_err();
+    assert_eq!(e.kind, ParseError::DateOutOfRange);
+    assert_eq!((e.line, e.column), (5, 1));
+    assert_eq!(e.source_line, "2021-09-31 * Tomod's");
+    assert_eq!(
+        e.to_string(),
+        "error: Out-of-range date\n --> 2021.ledger:5:1\n  |\n5 | 2021-09-31 * Tomod's\n  | ^"
+    );
+    assert_eq!(parser.next(), None);
+}
+
+#[test]
+fn test_ledger_parser_error_kinds() {
+    let cases = vec![
+        ("2021-09-16 * foo\n    Assets  100 JPY xyz\n", ParseError::InvalidAmount, 2, 21),
+        ("2021-09-16 * foo\n    -100 JPY\n", ParseError::MissingAccount, 2, 5),
+        ("2021-09-16 * (12 foo\n    Assets  100 JPY\n", ParseError::UnclosedCode, 1, 15),
+        ("2021-09-16 * foo\n\n", ParseError::MissingPosting, 2, 1),
+        ("2021/9 * foo\n    Assets  100 JPY\n", ParseError::DateFormat, 1, 1),
+        ("Assets  100 JPY\n", ParseError::BeginningLine, 1, 1),
+    ];
+
+    for (src, kind, line, column) in cases {
+        let e = LedgerParser::new(src)
+            .find_map(|item| item.err())
+            .unwrap();
+        assert_eq!((e.kind, e.line, e.column), (kind, line, column), "{}", src);
+    }
+}
+
+const LEDGER_TYPOS: &str = r#"2021-09-16 * 引き出し
+    資産:現金           1000 JPY
+    資産:普通預金:JP    -1000 JPY
+
+2021-09-31 * Tomod's
+    費用:食費           500 JPY
+    資産:現金
+
+2021-09-20 * Tomod's
+    費用:食費           500 JPY
+    資産:現金
+typo
+2021-09-21 * コンビニ
+    費用:食費           300 JPYY@
+    資産:現金
+2021-09-22 * コンビニ
+    費用:食費           200 JPY
+    資産:現金
+"#;
+
+#[test]
+fn test_ledger_parser_recovery() {
+    let (items, errors): (Vec<_>, Vec<_>) = LedgerParser::new(LEDGER_TYPOS)
+        .recovering()
+        .partition(|item| item.is_ok());
+
+    let transactions = items.into_iter()
+        .filter(|item| matches!(item, Ok(LedgerItem::Transaction(_))))
+        .count();
+    assert_eq!(transactions, 3);
+
+    let errors: Vec<_> = errors.into_iter()
+        .map(|e| e.unwrap_err())
+        .map(|e| (e.kind, e.line))
+        .collect();
+    assert_eq!(
+        errors,
+        vec![
+            (ParseError::DateOutOfRange, 5),
+            (ParseError::BeginningLine, 12),
+            (ParseError::InvalidAmount, 14),
+        ]
+    );
 }